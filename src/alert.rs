@@ -0,0 +1,458 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::PathBuf,
+    process::Command,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, bail};
+use log::{error, info};
+use serde::Deserialize;
+use zbus::{blocking::Connection, zvariant::Value};
+
+use crate::history::TimeWindow;
+
+/// Something sampled that an alert can watch.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Metric {
+    /// Average usage of all cores, in percent.
+    Cpu,
+    Memory,
+    Swap,
+    /// Used space of the disk mounted at the path, in percent.
+    Disk(PathBuf),
+    /// Bytes per second, summed over all disks or interfaces.
+    DiskRead,
+    DiskWrite,
+    Download,
+    Upload,
+}
+
+impl Metric {
+    pub fn is_rate(&self) -> bool {
+        matches!(
+            self,
+            Metric::DiskRead | Metric::DiskWrite | Metric::Download | Metric::Upload
+        )
+    }
+}
+
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "cpu" => Metric::Cpu,
+            "memory" => Metric::Memory,
+            "swap" => Metric::Swap,
+            "disk_read" => Metric::DiskRead,
+            "disk_write" => Metric::DiskWrite,
+            "download" => Metric::Download,
+            "upload" => Metric::Upload,
+            _ => match s.strip_prefix("disk:") {
+                Some(mount_point) => Metric::Disk(mount_point.into()),
+                None => bail!(
+                    "Unknown metric {s:?}, expected one of cpu, memory, swap, disk:<mount point>, \
+                     disk_read, disk_write, download, upload"
+                ),
+            },
+        })
+    }
+}
+
+impl TryFrom<String> for Metric {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Metric::Cpu => write!(f, "CPU"),
+            Metric::Memory => write!(f, "Memory"),
+            Metric::Swap => write!(f, "Swap"),
+            Metric::Disk(mount_point) => write!(f, "Disk {}", mount_point.display()),
+            Metric::DiskRead => write!(f, "Disk read"),
+            Metric::DiskWrite => write!(f, "Disk write"),
+            Metric::Download => write!(f, "Download"),
+            Metric::Upload => write!(f, "Upload"),
+        }
+    }
+}
+
+/// A condition to alert on, e.g. the root disk staying above 90% for 5 minutes.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    /// Notification summary, defaulting to a description of the rule.
+    pub name: Option<String>,
+    pub metric: Metric,
+    /// Percent, or bytes per second for rates.
+    pub above: f64,
+    /// The alert only clears once the metric drops below this, which defaults to `above`.
+    pub clear_below: Option<f64>,
+    /// How long the metric has to stay above the threshold before the alert fires.
+    #[serde(rename = "for")]
+    pub sustained: Option<TimeWindow>,
+    /// Minimum time between two notifications for this rule.
+    #[serde(default = "AlertRule::default_cooldown")]
+    pub cooldown: TimeWindow,
+    #[serde(default = "AlertRule::default_notify")]
+    pub notify: bool,
+    /// Run through `sh -c` when the alert fires, with `WIDGET_ALERT_SUMMARY` and
+    /// `WIDGET_ALERT_BODY` in its environment.
+    pub command: Option<String>,
+}
+
+impl AlertRule {
+    fn default_cooldown() -> TimeWindow {
+        TimeWindow::from_secs(15 * 60)
+    }
+
+    fn default_notify() -> bool {
+        true
+    }
+
+    pub fn summary(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{} is high", self.metric),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RuleState {
+    /// When the metric first went above the threshold, while it hasn't fired yet.
+    above_since: Option<Instant>,
+    firing: bool,
+    last_notified: Option<Instant>,
+}
+
+/// Tracks each alert rule across samples.
+pub struct AlertEngine {
+    /// The rules `states` belong to, in the same order.
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>,
+}
+
+impl AlertEngine {
+    pub fn new() -> Self {
+        AlertEngine {
+            rules: Vec::new(),
+            states: Vec::new(),
+        }
+    }
+
+    /// Updates every rule with its metric's current value and returns the indices of the rules
+    /// that should notify now. A metric without a value leaves its rule as it was.
+    pub fn evaluate(
+        &mut self,
+        rules: &[AlertRule],
+        values: &[Option<f64>],
+        now: Instant,
+    ) -> Vec<usize> {
+        // Rules are matched up by position, so start over if they were reconfigured.
+        if self.rules != rules {
+            self.rules = rules.to_vec();
+            self.states = rules.iter().map(|_| RuleState::default()).collect();
+        }
+
+        let mut fired = Vec::new();
+        for (i, (rule, state)) in rules.iter().zip(&mut self.states).enumerate() {
+            let Some(value) = values.get(i).copied().flatten() else {
+                continue;
+            };
+
+            if state.firing {
+                if value < rule.clear_below.unwrap_or(rule.above) {
+                    info!("Alert cleared: {}", rule.summary());
+                    state.firing = false;
+                }
+                continue;
+            }
+            if value <= rule.above {
+                state.above_since = None;
+                continue;
+            }
+
+            let since = *state.above_since.get_or_insert(now);
+            let sustained = rule.sustained.map_or(Duration::ZERO, |w| w.duration());
+            if now.saturating_duration_since(since) < sustained {
+                continue;
+            }
+            state.firing = true;
+            state.above_since = None;
+            let cooled_down = state
+                .last_notified
+                .is_none_or(|last| now.saturating_duration_since(last) >= rule.cooldown.duration());
+            if cooled_down {
+                state.last_notified = Some(now);
+                fired.push(i);
+            }
+        }
+        fired
+    }
+}
+
+/// Sends the notification and runs the command for a fired alert, in the background so a slow
+/// bus or command can't hold up drawing.
+pub fn dispatch(rule: &AlertRule, body: String) {
+    let summary = rule.summary();
+    let notify = rule.notify;
+    let command = rule.command.clone();
+    info!("Alert fired: {}: {}", summary, body);
+
+    thread::spawn(move || {
+        if notify {
+            let result = Connection::session()
+                .context("Failed to connect to the session bus")
+                .and_then(|connection| notify_desktop(&connection, &summary, &body));
+            if let Err(e) = result {
+                error!("Failed to send alert notification: {:#}", e);
+            }
+        }
+        if let Some(command) = command {
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .env("WIDGET_ALERT_SUMMARY", &summary)
+                .env("WIDGET_ALERT_BODY", &body)
+                .status();
+            match status {
+                Ok(status) if !status.success() => {
+                    error!("Alert command {:?} failed: {}", command, status)
+                }
+                Ok(_) => {}
+                Err(e) => error!("Failed to run alert command {:?}: {}", command, e),
+            }
+        }
+    });
+}
+
+/// Shows a critical notification through `org.freedesktop.Notifications`, returning its id.
+pub fn notify_desktop(connection: &Connection, summary: &str, body: &str) -> anyhow::Result<u32> {
+    const CRITICAL_URGENCY: u8 = 2;
+    let hints = HashMap::from([("urgency", Value::U8(CRITICAL_URGENCY))]);
+    let reply = connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "widget",
+                0u32, // replaces_id
+                "dialog-warning",
+                summary,
+                body,
+                Vec::<&str>::new(), // actions
+                hints,
+                -1i32, // expire_timeout, server default
+            ),
+        )
+        .context("Notify call failed")?;
+    reply
+        .body()
+        .deserialize()
+        .context("Unexpected reply to Notify")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead as _, BufReader},
+        process::Stdio,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::config::Config;
+
+    fn rule(toml: &str) -> AlertRule {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_parse_metric() {
+        assert_eq!("swap".parse::<Metric>().unwrap(), Metric::Swap);
+        assert_eq!(
+            "disk:/boot/efi".parse::<Metric>().unwrap(),
+            Metric::Disk("/boot/efi".into())
+        );
+        assert!("gpu".parse::<Metric>().is_err());
+    }
+
+    #[test]
+    fn test_parse_alerts() {
+        let config: Config = toml::from_str(
+            r#"
+            [[alerts]]
+            metric = "disk:/"
+            above = 90
+            for = "5m"
+
+            [[alerts]]
+            name = "Swapping"
+            metric = "swap"
+            above = 50
+            command = "logger swapping"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.alerts.len(), 2);
+        assert_eq!(config.alerts[0].summary(), "Disk / is high");
+        assert_eq!(config.alerts[0].sustained, Some(TimeWindow::from_secs(300)));
+        assert_eq!(config.alerts[1].summary(), "Swapping");
+        assert_eq!(config.alerts[1].command.as_deref(), Some("logger swapping"));
+    }
+
+    #[test]
+    fn test_alert_engine() {
+        let rules = [
+            rule("metric = \"disk:/\"\nabove = 90\nfor = \"5m\"\nclear_below = 85"),
+            rule("metric = \"swap\"\nabove = 50\ncooldown = \"10m\""),
+        ];
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut engine = AlertEngine::new();
+
+        // The disk has to stay full for five minutes before it fires, and only fires once.
+        assert!(
+            engine
+                .evaluate(&rules, &[Some(95.), None], at(0))
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate(&rules, &[Some(95.), None], at(200))
+                .is_empty()
+        );
+        assert_eq!(engine.evaluate(&rules, &[Some(95.), None], at(300)), [0]);
+        assert!(
+            engine
+                .evaluate(&rules, &[Some(95.), None], at(400))
+                .is_empty()
+        );
+
+        // Dipping between the two thresholds doesn't clear it.
+        assert!(
+            engine
+                .evaluate(&rules, &[Some(88.), None], at(500))
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate(&rules, &[Some(95.), None], at(600))
+                .is_empty()
+        );
+
+        // Swap fires right away, but not again within its cool-down after clearing.
+        assert_eq!(engine.evaluate(&rules, &[None, Some(60.)], at(700)), [1]);
+        assert!(
+            engine
+                .evaluate(&rules, &[None, Some(10.)], at(800))
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate(&rules, &[None, Some(60.)], at(900))
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate(&rules, &[None, Some(10.)], at(1000))
+                .is_empty()
+        );
+        assert_eq!(engine.evaluate(&rules, &[None, Some(60.)], at(1300)), [1]);
+    }
+
+    #[test]
+    fn test_alert_engine_reconfigured() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut engine = AlertEngine::new();
+        let rules = [rule("metric = \"swap\"\nabove = 50\nfor = \"5m\"")];
+        assert!(engine.evaluate(&rules, &[Some(60.)], at(0)).is_empty());
+
+        // A changed threshold starts the rule over, even with as many rules as before.
+        let rules = [rule("metric = \"swap\"\nabove = 40\nfor = \"5m\"")];
+        assert!(engine.evaluate(&rules, &[Some(60.)], at(200)).is_empty());
+        assert!(engine.evaluate(&rules, &[Some(60.)], at(300)).is_empty());
+        assert_eq!(engine.evaluate(&rules, &[Some(60.)], at(500)), [0]);
+    }
+
+    struct StubNotifications {
+        received: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl StubNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: &str,
+            _replaces_id: u32,
+            _app_icon: &str,
+            summary: &str,
+            body: &str,
+            _actions: Vec<&str>,
+            _hints: HashMap<&str, Value<'_>>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut received = self.received.lock().unwrap();
+            received.push((summary.to_owned(), body.to_owned()));
+            received.len() as u32
+        }
+    }
+
+    #[test]
+    fn test_notify_desktop() {
+        // Run against a private bus so the test doesn't pop up real notifications.
+        let Ok(mut daemon) = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+        else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let stub = StubNotifications {
+            received: received.clone(),
+        };
+        let _server = zbus::blocking::connection::Builder::address(address.trim())
+            .unwrap()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at("/org/freedesktop/Notifications", stub)
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = zbus::blocking::connection::Builder::address(address.trim())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let id = notify_desktop(&client, "Swap is high", "Swap is at 60.0%").unwrap();
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(
+            *received.lock().unwrap(),
+            [("Swap is high".to_owned(), "Swap is at 60.0%".to_owned())]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    f64::consts::PI,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+const PULSE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Shape of a transition over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    #[default]
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps linear progress in `[0, 1]` to eased progress.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1. - (1. - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4. * t * t * t,
+            Easing::EaseInOut => 1. - (-2. * t + 2.).powi(3) / 2.,
+        }
+    }
+}
+
+struct Tween {
+    from: f64,
+    to: f64,
+    start: Instant,
+}
+
+/// Interpolates drawn values toward their latest samples so the widget can be redrawn between
+/// samples without everything jumping once a second.
+pub struct Animator {
+    duration: Duration,
+    easing: Easing,
+    /// Time of the frame being drawn, so everything in it moves in step.
+    now: Instant,
+    last_sample: Instant,
+    /// Time until the next sample is due.
+    sample_interval: Duration,
+    tweens: HashMap<String, Tween>,
+    /// Whether something in the frame being drawn is pulsing and needs continuous redraws.
+    pulsing: bool,
+    /// Whether a graph in the frame being drawn is scrolling toward the next sample.
+    scrolling: Cell<bool>,
+}
+
+impl Animator {
+    pub fn new(duration: Duration, easing: Easing) -> Self {
+        let now = Instant::now();
+        Animator {
+            duration,
+            easing,
+            now,
+            last_sample: now,
+            sample_interval: Duration::ZERO,
+            tweens: HashMap::new(),
+            pulsing: false,
+            scrolling: Cell::new(false),
+        }
+    }
+
+    pub fn configure(&mut self, duration: Duration, easing: Easing) {
+        self.duration = duration;
+        self.easing = easing;
+    }
+
+    pub fn begin_frame(&mut self, now: Instant) {
+        self.now = now;
+        self.pulsing = false;
+        self.scrolling.set(false);
+    }
+
+    /// Marks a new sample, restarting the scroll of graphs that advance one point per sample
+    /// and take `interval` to reach the next one.
+    pub fn sample_taken(&mut self, now: Instant, interval: Duration) {
+        self.last_sample = now;
+        self.sample_interval = interval;
+    }
+
+    /// Progress from 0 to 1 through the time between samples, without easing so graphs scroll
+    /// at a steady pace. Frames keep coming for as long as this is called for every frame.
+    pub fn scroll_progress(&self) -> f64 {
+        self.scrolling.set(true);
+        if self.sample_interval.is_zero() {
+            return 1.;
+        }
+        let elapsed = self.now.saturating_duration_since(self.last_sample);
+        (elapsed.as_secs_f64() / self.sample_interval.as_secs_f64()).clamp(0., 1.)
+    }
+
+    /// Opacity for something pulsing once per `PULSE_PERIOD`. Frames keep coming for as long as
+    /// this is called for every frame.
+    pub fn pulse(&mut self) -> f64 {
+        self.pulsing = true;
+        let phase = self
+            .now
+            .saturating_duration_since(self.last_sample)
+            .as_secs_f64()
+            / PULSE_PERIOD.as_secs_f64();
+        0.65 + 0.35 * (phase * 2. * PI).cos()
+    }
+
+    /// Current value of the animated quantity `key` heading toward `target`. A changed target
+    /// starts a new transition from wherever the value is now.
+    pub fn value(&mut self, key: &str, target: f64) -> f64 {
+        // There's nothing sensible to interpolate to or from a NaN, and it would never compare
+        // equal to its previous target, so the animation would never settle.
+        if !target.is_finite() {
+            self.tweens.remove(key);
+            return target;
+        }
+        let Some(tween) = self.tweens.get(key) else {
+            self.tweens.insert(
+                key.to_owned(),
+                Tween {
+                    from: target,
+                    to: target,
+                    start: self.now,
+                },
+            );
+            return target;
+        };
+        let current = tween.from + (tween.to - tween.from) * self.progress(tween.start);
+        if tween.to != target {
+            self.tweens.insert(
+                key.to_owned(),
+                Tween {
+                    from: current,
+                    to: target,
+                    start: self.now,
+                },
+            );
+        }
+        current
+    }
+
+    /// Whether anything drawn in the last frame is still in motion and needs another frame soon.
+    pub fn is_animating(&self) -> bool {
+        let moving = |start: Instant| self.progress(start) < 1.;
+        self.pulsing
+            || (self.scrolling.get() && self.scroll_progress() < 1.)
+            || self
+                .tweens
+                .values()
+                .any(|tween| tween.from != tween.to && moving(tween.start))
+    }
+
+    fn progress(&self, start: Instant) -> f64 {
+        if self.duration.is_zero() {
+            return 1.;
+        }
+        let elapsed = self.now.saturating_duration_since(start);
+        self.easing
+            .apply(elapsed.as_secs_f64() / self.duration.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_easing() {
+        for easing in [Easing::Linear, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(-1.), 0.);
+            assert_eq!(easing.apply(0.), 0.);
+            assert_eq!(easing.apply(1.), 1.);
+            assert_eq!(easing.apply(2.), 1.);
+        }
+        assert_eq!(Easing::Linear.apply(0.25), 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_animator() {
+        let start = Instant::now();
+        let mut animator = Animator::new(Duration::from_millis(100), Easing::Linear);
+        animator.begin_frame(start);
+        animator.sample_taken(start, Duration::from_millis(100));
+        assert_eq!(animator.value("mem", 10.), 10.);
+
+        // The new target is reached over the animation duration.
+        assert_eq!(animator.value("mem", 20.), 10.);
+        animator.begin_frame(start + Duration::from_millis(50));
+        assert_eq!(animator.value("mem", 20.), 15.);
+        assert!(animator.is_animating());
+
+        // Retargeting mid-way continues from the current value.
+        assert_eq!(animator.value("mem", 5.), 15.);
+        animator.begin_frame(start + Duration::from_millis(100));
+        assert_eq!(animator.value("mem", 5.), 10.);
+        animator.begin_frame(start + Duration::from_millis(150));
+        assert_eq!(animator.value("mem", 5.), 5.);
+        assert!(!animator.is_animating());
+    }
+
+    #[test]
+    fn test_scroll_progress() {
+        let start = Instant::now();
+        let mut animator = Animator::new(Duration::from_millis(300), Easing::EaseOut);
+        animator.sample_taken(start, Duration::from_secs(2));
+
+        // Graphs keep scrolling at a steady pace long after value transitions are done.
+        animator.begin_frame(start + Duration::from_secs(1));
+        assert_eq!(animator.scroll_progress(), 0.5);
+        assert!(animator.is_animating());
+
+        animator.begin_frame(start + Duration::from_secs(3));
+        assert_eq!(animator.scroll_progress(), 1.);
+        assert!(!animator.is_animating());
+        // Nothing asks for more frames if no graph scrolls.
+        animator.begin_frame(start + Duration::from_secs(1));
+        assert!(!animator.is_animating());
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufRead as _},
+    sync::mpsc,
+    thread,
+};
+
+use itertools::Itertools as _;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, sample::Sample, threshold::Level, units::DataUnit};
+
+/// First line of the i3bar/swaybar protocol, before the endless array of status lines.
+pub const I3BAR_HEADER: &str = r#"{"version":1,"click_events":true}"#;
+
+/// One metric of the summary, formatted for a bar.
+#[derive(Debug)]
+pub struct Block {
+    pub name: &'static str,
+    pub level: Level,
+    pub short: String,
+    /// Shown in tooltips, or in place of `short` once the block is clicked.
+    pub long: String,
+}
+
+/// CPU, memory, root disk and network summary of `sample`, formatted and colored like the widget.
+pub fn blocks(sample: &Sample, config: &Config) -> Vec<Block> {
+    let thresholds = &config.thresholds;
+    let sizes = config.units.format(DataUnit::Bytes);
+    let disk_units = config.units.format(config.units.disk);
+    let network_units = config.units.format(config.units.network);
+    let mut blocks = Vec::new();
+
+    let busiest_core = sample
+        .cores
+        .iter()
+        .map(|core| core.usage)
+        .fold(0., f64::max);
+    blocks.push(Block {
+        name: "cpu",
+        level: sample
+            .cpu
+            .map_or(Level::Normal, |cpu| thresholds.cpu.level(cpu)),
+        short: format!("\u{f4bc} {}", percent_text(sample.cpu)),
+        long: format!(
+            "\u{f4bc} {} over {} cores, busiest at {busiest_core:.1}%",
+            percent_text(sample.cpu),
+            sample.cores.len()
+        ),
+    });
+
+    let memory = sample.memory.and_then(|memory| memory.percent());
+    let swap = sample.swap.and_then(|swap| swap.percent());
+    let swap_text = swap.map_or("no swap".to_owned(), |swap| format!("swap {swap:.1}%"));
+    let memory_total = sample
+        .memory
+        .map_or("n/a".to_owned(), |memory| sizes.amount(memory.total as f64));
+    blocks.push(Block {
+        name: "memory",
+        level: thresholds
+            .memory
+            .level(memory.unwrap_or_default())
+            .max(thresholds.swap.level(swap.unwrap_or_default())),
+        short: format!("\u{efc5} {}", percent_text(memory)),
+        long: format!(
+            "\u{efc5} {} of {memory_total}, {swap_text}",
+            percent_text(memory)
+        ),
+    });
+
+    if let Some(root) = sample.disk("/") {
+        let used = root.space.percent();
+        blocks.push(Block {
+            name: "disk",
+            level: thresholds.disk.level(used.unwrap_or_default()),
+            short: format!("\u{f02ca} {}", percent_text(used)),
+            long: format!(
+                "\u{f02ca} {} of {}  \u{f139} {}  \u{f13a} {}",
+                percent_text(used),
+                sizes.amount(root.space.total as f64),
+                disk_units.rate_or_na(sample.disk_read),
+                disk_units.rate_or_na(sample.disk_write)
+            ),
+        });
+    }
+
+    let network = format!(
+        "\u{f0002} \u{f139} {}  \u{f13a} {}",
+        network_units.rate_or_na(sample.upload),
+        network_units.rate_or_na(sample.download)
+    );
+    blocks.push(Block {
+        name: "network",
+        level: Level::Normal,
+        short: network.clone(),
+        long: network,
+    });
+    blocks
+}
+
+/// Metrics that can't be measured are shown as "n/a" rather than 0.
+fn percent_text(percent: Option<f64>) -> String {
+    percent.map_or("n/a".to_owned(), |percent| format!("{percent:.1}%"))
+}
+
+/// A status line for a Waybar custom module with `"return-type": "json"`.
+#[derive(Debug, Serialize)]
+pub struct WaybarStatus {
+    pub text: String,
+    pub tooltip: String,
+    /// The most concerning level of any block, for styling in Waybar's CSS.
+    pub class: Level,
+    /// CPU usage, for `format-icons`.
+    pub percentage: u8,
+}
+
+pub fn waybar_status(sample: &Sample, config: &Config) -> WaybarStatus {
+    let blocks = blocks(sample, config);
+    WaybarStatus {
+        text: blocks.iter().map(|block| &block.short).join("  "),
+        tooltip: blocks.iter().map(|block| &block.long).join("\n"),
+        class: blocks
+            .iter()
+            .map(|block| block.level)
+            .max()
+            .unwrap_or(Level::Normal),
+        percentage: sample.cpu.unwrap_or_default().round().clamp(0., 100.) as u8,
+    }
+}
+
+/// One block of an i3bar/swaybar status line.
+#[derive(Debug, Serialize)]
+pub struct I3barBlock {
+    pub name: &'static str,
+    pub full_text: String,
+    pub short_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub urgent: bool,
+}
+
+/// Status line for i3bar/swaybar, with the blocks named in `expanded` showing their long text.
+pub fn i3bar_status(
+    sample: &Sample,
+    config: &Config,
+    expanded: &HashSet<String>,
+) -> Vec<I3barBlock> {
+    blocks(sample, config)
+        .into_iter()
+        .map(|block| {
+            let color = (block.level != Level::Normal).then(|| {
+                let (red, green, blue) = block.level.color((1., 1., 1.));
+                let byte = |c: f64| (c * 255.).round() as u8;
+                format!("#{:02x}{:02x}{:02x}", byte(red), byte(green), byte(blue))
+            });
+            I3barBlock {
+                name: block.name,
+                full_text: if expanded.contains(block.name) {
+                    block.long
+                } else {
+                    block.short.clone()
+                },
+                short_text: block.short,
+                color,
+                urgent: block.level == Level::Critical,
+            }
+        })
+        .collect()
+}
+
+/// A click on one of our blocks, as sent by i3bar/swaybar on stdin.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct ClickEvent {
+    pub name: Option<String>,
+    pub button: u32,
+}
+
+impl ClickEvent {
+    /// Parses a line of the click event stream, which is an endless JSON array with one event
+    /// per line.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim().trim_start_matches(['[', ',']).trim();
+        if line.is_empty() {
+            return None;
+        }
+        serde_json::from_str(line)
+            .inspect_err(|e| debug!("Ignoring click event {line:?}: {e}"))
+            .ok()
+    }
+}
+
+/// Reads click events from stdin in the background, until it is closed.
+pub fn read_clicks() -> mpsc::Receiver<ClickEvent> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(click) = ClickEvent::parse(&line)
+                && sender.send(click).is_err()
+            {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::{DiskSample, Usage};
+
+    fn sample() -> Sample {
+        Sample {
+            cpu: Some(42.4),
+            memory: Usage::new(97, 100),
+            disks: vec![DiskSample {
+                name: "/dev/vda".to_owned(),
+                mount_point: "/".into(),
+                space: Usage {
+                    used: 50,
+                    total: 100,
+                },
+                read_bytes: 0,
+                written_bytes: 0,
+            }],
+            download: Some(2048.),
+            upload: Some(0.),
+            ..Sample::default()
+        }
+    }
+
+    #[test]
+    fn test_waybar_status() {
+        let status = serde_json::to_value(waybar_status(&sample(), &Config::default())).unwrap();
+        assert_eq!(
+            status["text"],
+            "\u{f4bc} 42.4%  \u{efc5} 97.0%  \u{f02ca} 50.0%  \
+             \u{f0002} \u{f139} 0B/s  \u{f13a} 2.0KiB/s"
+        );
+        assert_eq!(status["class"], "critical");
+        assert_eq!(status["percentage"], 42);
+        assert!(
+            status["tooltip"]
+                .as_str()
+                .unwrap()
+                .contains("97.0% of 100B, no swap")
+        );
+    }
+
+    #[test]
+    fn test_missing_metrics() {
+        let sample = Sample {
+            disks: vec![DiskSample {
+                space: Usage { used: 0, total: 0 },
+                ..sample().disks[0].clone()
+            }],
+            ..Sample::default()
+        };
+        let status = waybar_status(&sample, &Config::default());
+        assert_eq!(
+            status.text,
+            "\u{f4bc} n/a  \u{efc5} n/a  \u{f02ca} n/a  \u{f0002} \u{f139} n/a  \u{f13a} n/a"
+        );
+        assert_eq!(status.class, Level::Normal);
+        assert!(status.tooltip.contains("n/a of n/a, no swap"));
+    }
+
+    #[test]
+    fn test_i3bar_status() {
+        let status = i3bar_status(
+            &sample(),
+            &Config::default(),
+            &HashSet::from(["disk".to_owned()]),
+        );
+        let memory = &status[1];
+        assert_eq!(memory.color.as_deref(), Some("#ff4040"));
+        assert!(memory.urgent);
+        assert!(status[0].color.is_none());
+        assert_eq!(status[2].short_text, "\u{f02ca} 50.0%");
+        assert!(status[2].full_text.starts_with("\u{f02ca} 50.0% of 100B"));
+    }
+
+    #[test]
+    fn test_parse_click_event() {
+        assert_eq!(ClickEvent::parse("["), None);
+        let click = r#"{"name":"disk","instance":null,"button":1,"x":10,"y":2}"#;
+        let expected = Some(ClickEvent {
+            name: Some("disk".to_owned()),
+            button: 1,
+        });
+        assert_eq!(ClickEvent::parse(click), expected);
+        assert_eq!(ClickEvent::parse(&format!(",{click}")), expected);
+        assert_eq!(ClickEvent::parse("garbage"), None);
+    }
+}
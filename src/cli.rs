@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use itertools::Itertools as _;
+use sysinfo::{Disks, Networks};
+
+use crate::{
+    history::TimeWindow,
+    ipc::{Layer, Request},
+    metrics::ListenAddr,
+    stream::Format,
+    units::UnitFormat,
+};
+
+/// System monitor drawn on the desktop background of wlroots compositors.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Config file to use instead of `$XDG_CONFIG_HOME/widget/config.toml`.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Draw the widget, which is also what happens without a subcommand.
+    Run(RunArgs),
+    /// Draw the widget once into a PNG file, without a compositor.
+    Snapshot(SnapshotArgs),
+    /// Print a sample every interval for scripts or status bars, without drawing anything.
+    Stream(StreamArgs),
+    /// Check that the config file parses, without starting the widget.
+    CheckConfig,
+    /// List the compositor's outputs, for `run --output`.
+    ListOutputs,
+    /// List the mounted disks, for disk metrics in alerts.
+    ListDisks,
+    /// List the network interfaces.
+    ListInterfaces,
+    /// Send a command to the running widget over its control socket.
+    #[command(subcommand)]
+    Msg(Request),
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Name of the output to draw on, e.g. "DP-1". Defaults to the compositor's choice.
+    #[arg(long)]
+    pub output: Option<String>,
+    #[arg(long, value_enum, default_value = "background")]
+    pub layer: Layer,
+    /// Time between samples in whole seconds, e.g. "2s". Graphs are kept per second, so sampling
+    /// faster wouldn't show.
+    #[arg(long, default_value = "1s")]
+    pub interval: TimeWindow,
+    /// Serve Prometheus metrics at `/metrics` on this address, e.g. "localhost:9101" or
+    /// "unix:/run/user/1000/widget-metrics.sock".
+    #[arg(long)]
+    pub metrics: Option<ListenAddr>,
+}
+
+impl Default for RunArgs {
+    fn default() -> Self {
+        RunArgs {
+            output: None,
+            layer: Layer::Background,
+            interval: TimeWindow::from_secs(1),
+            metrics: None,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    /// Where to write the PNG.
+    #[arg(default_value = "widget.png")]
+    pub path: PathBuf,
+    #[arg(long, default_value_t = 1920)]
+    pub width: u32,
+    #[arg(long, default_value_t = 1080)]
+    pub height: u32,
+    /// Time between the two samples needed for rates in whole seconds, e.g. "2s".
+    #[arg(long, default_value = "1s")]
+    pub interval: TimeWindow,
+}
+
+#[derive(Debug, Args)]
+pub struct StreamArgs {
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: Format,
+    /// Time between samples in whole seconds, e.g. "5s".
+    #[arg(long, default_value = "1s")]
+    pub interval: TimeWindow,
+    /// Stop after this many samples instead of running until stdout is closed.
+    #[arg(long)]
+    pub count: Option<u64>,
+}
+
+pub fn list_disks(units: UnitFormat) {
+    let disks = Disks::new_with_refreshed_list();
+    for disk in disks.iter().sorted_by_key(|disk| disk.mount_point()) {
+        println!(
+            "{}\t{}\t{}\t{} of {} available",
+            disk.mount_point().display(),
+            disk.name().to_string_lossy(),
+            disk.file_system().to_string_lossy(),
+            units.amount(disk.available_space() as f64),
+            units.amount(disk.total_space() as f64),
+        );
+    }
+}
+
+pub fn list_interfaces(units: UnitFormat) {
+    let networks = Networks::new_with_refreshed_list();
+    for (name, network) in networks.iter().sorted_by_key(|(name, _)| *name) {
+        let addresses = network
+            .ip_networks()
+            .iter()
+            .map(|ip| format!("{}/{}", ip.addr, ip.prefix))
+            .join(", ");
+        println!(
+            "{name}\t{}\t{} received, {} sent\t{addresses}",
+            network.mac_address(),
+            units.amount(network.total_received() as f64),
+            units.amount(network.total_transmitted() as f64),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::ipc::Panel;
+
+    #[test]
+    fn test_parse_cli() {
+        let cli = Cli::try_parse_from(["widget"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from([
+            "widget",
+            "run",
+            "--config",
+            "/tmp/widget.toml",
+            "--layer",
+            "bottom",
+            "--interval",
+            "2s",
+        ])
+        .unwrap();
+        assert_eq!(cli.config, Some("/tmp/widget.toml".into()));
+        let Some(Command::Run(args)) = cli.command else {
+            panic!("expected run, got {:?}", cli.command);
+        };
+        assert_eq!(args.layer, Layer::Bottom);
+        assert_eq!(args.interval.duration(), Duration::from_secs(2));
+
+        let cli = Cli::try_parse_from(["widget", "msg", "toggle", "network"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Msg(Request::Toggle {
+                panel: Panel::Network
+            }))
+        ));
+        assert!(Cli::try_parse_from(["widget", "msg", "toggle"]).is_err());
+        assert!(Cli::try_parse_from(["widget", "run", "--interval", "0s"]).is_err());
+        assert!(Cli::try_parse_from(["widget", "run", "--interval", "500ms"]).is_err());
+    }
+}
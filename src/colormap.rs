@@ -0,0 +1,94 @@
+use serde::Deserialize;
+
+/// Colors a value between 0 and 1, e.g. a core's usage in the heatmap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Colormap {
+    /// Dark blue through green to yellow, readable in grayscale and by most color blind people.
+    #[default]
+    Viridis,
+    /// Black through purple and orange to pale yellow.
+    Magma,
+    /// Transparent to the widget's pink, so idle cores disappear into the background.
+    Pink,
+    Gray,
+}
+
+impl Colormap {
+    /// Red, green, blue and alpha of `value`, clamped to between 0 and 1.
+    pub fn color(self, value: f64) -> (f64, f64, f64, f64) {
+        let value = if value.is_nan() {
+            0.
+        } else {
+            value.clamp(0., 1.)
+        };
+        match self {
+            Colormap::Viridis => opaque(interpolate(&VIRIDIS, value)),
+            Colormap::Magma => opaque(interpolate(&MAGMA, value)),
+            Colormap::Pink => (212. / 255., 79. / 255., 126. / 255., value),
+            Colormap::Gray => (value, value, value, 1.),
+        }
+    }
+}
+
+/// Evenly spaced stops sampled from matplotlib's colormaps of the same names.
+const VIRIDIS: [(f64, f64, f64); 5] = [
+    (0.267, 0.005, 0.329),
+    (0.229, 0.322, 0.546),
+    (0.128, 0.567, 0.551),
+    (0.369, 0.789, 0.383),
+    (0.993, 0.906, 0.144),
+];
+const MAGMA: [(f64, f64, f64); 5] = [
+    (0.001, 0.000, 0.014),
+    (0.316, 0.071, 0.485),
+    (0.716, 0.215, 0.475),
+    (0.987, 0.535, 0.383),
+    (0.987, 0.991, 0.750),
+];
+
+fn interpolate(stops: &[(f64, f64, f64)], value: f64) -> (f64, f64, f64) {
+    let position = value * (stops.len() - 1) as f64;
+    let i = (position.floor() as usize).min(stops.len() - 2);
+    let t = position - i as f64;
+    let (a, b) = (stops[i], stops[i + 1]);
+    let mix = |a: f64, b: f64| a + (b - a) * t;
+    (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+fn opaque((red, green, blue): (f64, f64, f64)) -> (f64, f64, f64, f64) {
+    (red, green, blue, 1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, history::TimeWindow};
+
+    #[test]
+    fn test_colormap() {
+        let (red, green, blue, alpha) = Colormap::Viridis.color(0.);
+        assert_eq!((red, green, blue, alpha), (0.267, 0.005, 0.329, 1.));
+        assert_eq!(Colormap::Viridis.color(1.).1, 0.906);
+        assert_eq!(Colormap::Viridis.color(2.), Colormap::Viridis.color(1.));
+        assert_eq!(
+            Colormap::Viridis.color(f64::NAN),
+            Colormap::Viridis.color(0.)
+        );
+        // Halfway between the second and third stops.
+        let (_, green, _, _) = Colormap::Magma.color(0.375);
+        assert!((green - 0.143).abs() < 1e-9);
+        assert_eq!(Colormap::Gray.color(0.25), (0.25, 0.25, 0.25, 1.));
+        assert_eq!(Colormap::Pink.color(0.5).3, 0.5);
+    }
+
+    #[test]
+    fn test_parse_heatmap_config() {
+        let config: Config =
+            toml::from_str("[heatmap]\nenabled = true\ncolormap = \"magma\"").unwrap();
+        assert!(config.heatmap.enabled);
+        assert_eq!(config.heatmap.window, TimeWindow::from_secs(2 * 60));
+        assert_eq!(config.heatmap.colormap, Colormap::Magma);
+        assert!(toml::from_str::<Config>("[heatmap]\ncolormap = \"jet\"").is_err());
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context as _, Result};
+use serde::Deserialize;
+
+use crate::{
+    alert::AlertRule,
+    animation::Easing,
+    colormap::Colormap,
+    cores::{CoreGrouping, CoreLayout},
+    graph::{Scale, Style},
+    history::TimeWindow,
+    threshold::Thresholds,
+    units::{DataUnit, UnitBase, UnitFormat},
+};
+
+/// Settings read from `$XDG_CONFIG_HOME/widget/config.toml`. Every field is optional and the
+/// defaults reproduce the widget's original look.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub cpu: CpuConfig,
+    pub heatmap: HeatmapConfig,
+    pub disk: GraphConfig,
+    pub network: GraphConfig,
+    pub sensors: SensorsConfig,
+    pub battery: BatteryConfig,
+    pub processes: ProcessesConfig,
+    pub load: LoadConfig,
+    pub units: UnitsConfig,
+    pub animation: AnimationConfig,
+    pub thresholds: ThresholdsConfig,
+    pub alerts: Vec<AlertRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CpuConfig {
+    /// How much history the ring around the gauge shows.
+    pub window: TimeWindow,
+    /// Split the core arcs and the history ring into user, nice, system, I/O wait, interrupt
+    /// and steal time.
+    pub breakdown: bool,
+    /// Show each core's clock relative to its highest alongside its usage.
+    pub frequency: bool,
+    pub layout: CoreLayout,
+    pub group: CoreGrouping,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        CpuConfig {
+            window: TimeWindow::from_secs(50),
+            breakdown: false,
+            frequency: false,
+            layout: CoreLayout::Auto,
+            group: CoreGrouping::Thread,
+        }
+    }
+}
+
+/// Usage of each core over time, drawn above the gauge.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeatmapConfig {
+    pub enabled: bool,
+    /// How much history each core's row shows.
+    pub window: TimeWindow,
+    pub colormap: Colormap,
+}
+
+impl Default for HeatmapConfig {
+    fn default() -> Self {
+        HeatmapConfig {
+            enabled: false,
+            window: TimeWindow::from_secs(2 * 60),
+            colormap: Colormap::Viridis,
+        }
+    }
+}
+
+/// Settings for the disk and network graphs.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphConfig {
+    /// How much history the graph shows.
+    pub window: TimeWindow,
+    pub style: Style,
+    /// Fill bars and areas with a gradient that fades toward the axis.
+    pub gradient: bool,
+    pub scale: Scale,
+    /// Top of the axis in bytes per second for the fixed and log scales.
+    pub max: Option<f64>,
+    /// Label the top of the axis with its value, if the series share one.
+    pub max_label: bool,
+    /// Number of horizontal bands marked by gridlines, 0 for none.
+    pub gridlines: u32,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig {
+            window: TimeWindow::from_secs(150),
+            style: Style::Bars,
+            gradient: false,
+            scale: Scale::Independent,
+            max: None,
+            max_label: false,
+            gridlines: 0,
+        }
+    }
+}
+
+/// Which temperatures and fans the sensors panel shows.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorsConfig {
+    /// Sensors whose label contains any of these, ignoring case, e.g. "Package id 0" or
+    /// "nvme Composite". Empty shows every sensor.
+    pub labels: Vec<String>,
+    /// How much history each sensor's graph shows.
+    pub window: TimeWindow,
+}
+
+impl Default for SensorsConfig {
+    fn default() -> Self {
+        SensorsConfig {
+            labels: Vec::new(),
+            window: TimeWindow::from_secs(5 * 60),
+        }
+    }
+}
+
+impl SensorsConfig {
+    pub fn selects(&self, label: &str) -> bool {
+        let label = label.to_lowercase();
+        self.labels.is_empty()
+            || self
+                .labels
+                .iter()
+                .any(|pattern| label.contains(&pattern.to_lowercase()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryConfig {
+    /// How long the power draw is averaged over to estimate the time to empty or full.
+    pub window: TimeWindow,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        BatteryConfig {
+            window: TimeWindow::from_secs(5 * 60),
+        }
+    }
+}
+
+/// The load average shown inside the CPU gauge.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadConfig {
+    /// Show the load per core instead of the raw run queue length.
+    pub normalize: bool,
+    /// Draw the 1-minute load over `window` above the numbers.
+    pub graph: bool,
+    pub window: TimeWindow,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig {
+            normalize: false,
+            graph: false,
+            window: TimeWindow::from_secs(15 * 60),
+        }
+    }
+}
+
+/// The top processes panel.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessesConfig {
+    /// How many processes to list by CPU and by memory, 0 to never look at processes.
+    pub count: usize,
+    /// Time between walks of the process list, which is slower than the other metrics.
+    pub interval: TimeWindow,
+}
+
+impl Default for ProcessesConfig {
+    fn default() -> Self {
+        ProcessesConfig {
+            count: 5,
+            interval: TimeWindow::from_secs(5),
+        }
+    }
+}
+
+/// How byte counts and rates are written in labels.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnitsConfig {
+    pub base: UnitBase,
+    pub precision: usize,
+    pub disk: DataUnit,
+    pub network: DataUnit,
+}
+
+impl Default for UnitsConfig {
+    fn default() -> Self {
+        UnitsConfig {
+            base: UnitBase::Iec,
+            precision: 1,
+            disk: DataUnit::Bytes,
+            network: DataUnit::Bytes,
+        }
+    }
+}
+
+impl UnitsConfig {
+    pub fn format(&self, unit: DataUnit) -> UnitFormat {
+        UnitFormat {
+            base: self.base,
+            unit,
+            precision: self.precision,
+        }
+    }
+}
+
+/// Transitions between samples.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimationConfig {
+    /// Length of each transition, 0 to jump straight to new values.
+    pub duration_ms: u64,
+    pub easing: Easing,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        AnimationConfig {
+            duration_ms: 300,
+            easing: Easing::EaseOut,
+        }
+    }
+}
+
+impl AnimationConfig {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+}
+
+/// When metrics change color and get a warning icon, as percentages or degrees Celsius.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThresholdsConfig {
+    /// Applied to each core's arc as well as the overall usage.
+    pub cpu: Thresholds,
+    pub memory: Thresholds,
+    pub swap: Thresholds,
+    pub disk: Thresholds,
+    /// Load average as a percentage of the number of CPUs, so the default warns once there are
+    /// more runnable processes than CPUs.
+    pub load: Thresholds,
+    /// Sensor temperatures. A sensor is also critical past the limit its hardware reports.
+    pub temperature: Thresholds,
+    /// Make metrics past their critical threshold pulse.
+    pub pulse: bool,
+}
+
+impl Default for ThresholdsConfig {
+    fn default() -> Self {
+        ThresholdsConfig {
+            cpu: Thresholds::default(),
+            memory: Thresholds::new(80., 95.),
+            swap: Thresholds::new(80., 95.),
+            disk: Thresholds::new(80., 95.),
+            load: Thresholds::new(100., 200.),
+            temperature: Thresholds::new(80., 95.),
+            pulse: false,
+        }
+    }
+}
+
+impl Config {
+    /// Where the config file is expected to live, whether or not it exists.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("widget").join("config.toml"))
+    }
+
+    /// Reads the config at `path`, falling back to the defaults if there is no file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [disk]
+            window = "1h"
+
+            [network]
+            window = "24h"
+
+            [battery]
+            window = "10m"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.cpu.window, TimeWindow::from_secs(50));
+        assert_eq!(config.disk.window, TimeWindow::from_secs(60 * 60));
+        assert_eq!(config.network.window, TimeWindow::from_secs(24 * 60 * 60));
+        assert_eq!(config.battery.window, TimeWindow::from_secs(10 * 60));
+
+        assert!(toml::from_str::<Config>("[cpu]\nwindow = \"soon\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_panels() {
+        let config: Config = toml::from_str(
+            r#"
+            [processes]
+            count = 3
+
+            [load]
+            graph = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.processes.count, 3);
+        assert_eq!(config.processes.interval, TimeWindow::from_secs(5));
+        assert!(config.load.graph && !config.load.normalize);
+    }
+
+    #[test]
+    fn test_sensors_selects() {
+        let config: Config =
+            toml::from_str("[sensors]\nlabels = [\"Package id\", \"nvme\"]").unwrap();
+        assert!(config.sensors.selects("coretemp Package id 0"));
+        assert!(config.sensors.selects("NVMe Composite"));
+        assert!(!config.sensors.selects("thinkpad fan1"));
+        assert!(Config::default().sensors.selects("thinkpad fan1"));
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use itertools::Itertools as _;
+use serde::Deserialize;
+
+use crate::{
+    cpu_time::CpuBreakdown,
+    sample::CoreSample,
+    topology::{Topology, cpu_index},
+};
+
+/// Up to this many rings of core arcs fit between the gauge's edge and its text.
+pub const MAX_RINGS: usize = 8;
+
+/// How the per-core usage is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoreLayout {
+    /// Rings while they fit, the grid past that.
+    #[default]
+    Auto,
+    /// A ring per two cores inside the gauge, each core filling a quarter of it.
+    Rings,
+    /// A cell per core above the gauge, brighter the busier it is.
+    Grid,
+}
+
+impl CoreLayout {
+    /// Whether `cores` are drawn as rings rather than a grid.
+    pub fn rings(self, cores: usize) -> bool {
+        match self {
+            CoreLayout::Auto => cores.div_ceil(2) <= MAX_RINGS,
+            CoreLayout::Rings => true,
+            CoreLayout::Grid => false,
+        }
+    }
+}
+
+/// Which CPUs the per-core view shows separately.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoreGrouping {
+    /// Every logical CPU, including each SMT sibling.
+    #[default]
+    Thread,
+    /// Physical cores, averaging their SMT siblings.
+    Core,
+    /// NUMA nodes, averaging all of their CPUs.
+    Node,
+}
+
+/// Averages `cores` into one entry per group, named like "core3" or "node1". Groups keep the
+/// order of their first CPU, and CPUs missing from the topology are kept as they are.
+pub fn group_cores(
+    cores: &[CoreSample],
+    topology: &Topology,
+    grouping: CoreGrouping,
+) -> Vec<CoreSample> {
+    if grouping == CoreGrouping::Thread {
+        return cores.to_vec();
+    }
+
+    // Groups are named when first seen, so core numbers follow the order of the CPUs.
+    let mut groups: Vec<(Option<String>, Vec<&CoreSample>)> = Vec::new();
+    let mut positions = HashMap::new();
+    for core in cores {
+        let Some(place) = cpu_index(&core.name).and_then(|cpu| topology.place(cpu)) else {
+            groups.push((None, vec![core]));
+            continue;
+        };
+        let (key, name) = match grouping {
+            CoreGrouping::Node => ((place.node, 0), format!("node{}", place.node)),
+            _ => (
+                (place.package, place.core),
+                format!("core{}", positions.len()),
+            ),
+        };
+        let position = *positions.entry(key).or_insert_with(|| {
+            groups.push((Some(name), Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(core);
+    }
+
+    groups
+        .into_iter()
+        .map(|(name, members)| {
+            let Some(name) = name else {
+                return members[0].clone();
+            };
+            let count = members.len() as f64;
+            let breakdowns = members
+                .iter()
+                .map(|core| core.breakdown)
+                .collect::<Option<Vec<_>>>();
+            CoreSample {
+                name,
+                usage: members.iter().map(|core| core.usage).sum::<f64>() / count,
+                breakdown: breakdowns.map(|breakdowns| average(&breakdowns)),
+                // Nodes can mix P-cores and E-cores.
+                kind: members
+                    .iter()
+                    .map(|core| core.kind)
+                    .all_equal_value()
+                    .ok()
+                    .flatten(),
+                frequency: average_of(members.iter().filter_map(|core| core.frequency)),
+                max_frequency: members
+                    .iter()
+                    .filter_map(|core| core.max_frequency)
+                    .reduce(f64::max),
+                throttled: members.iter().any(|core| core.throttled),
+            }
+        })
+        .collect()
+}
+
+fn average_of(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0., 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+fn average(breakdowns: &[CpuBreakdown]) -> CpuBreakdown {
+    let count = breakdowns.len() as f64;
+    let sums = breakdowns.iter().fold([0.; 6], |mut sums, breakdown| {
+        for (sum, value) in sums.iter_mut().zip(breakdown.values()) {
+            *sum += value;
+        }
+        sums
+    });
+    let [user, nice, system, iowait, irq, steal] = sums.map(|sum| sum / count);
+    CpuBreakdown {
+        user,
+        nice,
+        system,
+        iowait,
+        irq,
+        steal,
+    }
+}
+
+/// Columns and rows of a grid of `cells` that is about `aspect` times wider than it is tall.
+pub fn grid_shape(cells: usize, aspect: f64) -> (usize, usize) {
+    let columns = ((cells as f64 * aspect).sqrt().ceil() as usize).clamp(1, cells.max(1));
+    (columns, cells.div_ceil(columns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, topology::CpuPlace};
+
+    #[test]
+    fn test_group_cores() {
+        // cpu0 and cpu2 are siblings on node 0, cpu1 and cpu3 on node 1.
+        let topology = (0..4)
+            .map(|cpu| {
+                let place = CpuPlace {
+                    package: 0,
+                    core: cpu as u32 % 2,
+                    node: cpu as u32 % 2,
+                    kind: None,
+                };
+                (cpu, place)
+            })
+            .collect::<Topology>();
+
+        let core = |name: &str, usage, user| CoreSample {
+            name: name.to_owned(),
+            usage,
+            breakdown: Some(CpuBreakdown {
+                user,
+                ..CpuBreakdown::default()
+            }),
+            kind: None,
+            frequency: None,
+            max_frequency: None,
+            throttled: false,
+        };
+        let cores = [
+            core("cpu0", 10., 10.),
+            core("cpu1", 20., 20.),
+            core("cpu2", 30., 30.),
+            core("cpu3", 40., 40.),
+            core("cpu4", 50., 50.),
+        ];
+        let summary = |grouping| {
+            group_cores(&cores, &topology, grouping)
+                .into_iter()
+                .map(|core| (core.name, core.usage, core.breakdown.unwrap().user))
+                .collect_vec()
+        };
+        assert_eq!(summary(CoreGrouping::Thread).len(), 5);
+        // cpu4 isn't in the topology, so it stays on its own.
+        assert_eq!(
+            summary(CoreGrouping::Core),
+            [
+                ("core0".to_owned(), 20., 20.),
+                ("core1".to_owned(), 30., 30.),
+                ("cpu4".to_owned(), 50., 50.),
+            ]
+        );
+        assert_eq!(
+            summary(CoreGrouping::Node)[..2],
+            [
+                ("node0".to_owned(), 20., 20.),
+                ("node1".to_owned(), 30., 30.)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_core_layout() {
+        assert!(CoreLayout::Auto.rings(16));
+        assert!(!CoreLayout::Auto.rings(64));
+        assert!(CoreLayout::Rings.rings(64));
+        assert!(!CoreLayout::Grid.rings(2));
+        assert_eq!(grid_shape(64, 4.), (16, 4));
+        assert_eq!(grid_shape(6, 4.), (5, 2));
+        assert_eq!(grid_shape(1, 4.), (1, 1));
+    }
+
+    #[test]
+    fn test_parse_cpu_config() {
+        let config: Config =
+            toml::from_str("[cpu]\nfrequency = true\nlayout = \"grid\"\ngroup = \"node\"").unwrap();
+        assert!(config.cpu.frequency);
+        assert!(!config.cpu.breakdown);
+        assert_eq!(config.cpu.layout, CoreLayout::Grid);
+        assert_eq!(config.cpu.group, CoreGrouping::Node);
+    }
+}
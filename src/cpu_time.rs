@@ -0,0 +1,183 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::Serialize;
+
+/// Where CPU time went since the previous sample, in percent of the elapsed time. Idle time
+/// makes up the rest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct CpuBreakdown {
+    pub user: f64,
+    /// User time of processes with a positive nice value.
+    pub nice: f64,
+    pub system: f64,
+    /// Idle while waiting for disk I/O.
+    pub iowait: f64,
+    /// Hard and soft interrupts.
+    pub irq: f64,
+    /// Taken by the hypervisor for other guests.
+    pub steal: f64,
+}
+
+impl CpuBreakdown {
+    pub const MODES: [&str; 6] = ["user", "nice", "system", "iowait", "irq", "steal"];
+
+    /// Percentages in the order of `MODES`.
+    pub fn values(&self) -> [f64; 6] {
+        [
+            self.user,
+            self.nice,
+            self.system,
+            self.iowait,
+            self.irq,
+            self.steal,
+        ]
+    }
+}
+
+/// Cumulative ticks of one CPU line of `/proc/stat`.
+#[derive(Clone, Copy, Debug, Default)]
+struct Ticks {
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    steal: u64,
+}
+
+impl Ticks {
+    fn parse(fields: &[&str]) -> Option<Self> {
+        let field = |i: usize| fields.get(i).map_or(Some(0), |f| f.parse().ok());
+        Some(Ticks {
+            user: field(0)?,
+            nice: field(1)?,
+            system: field(2)?,
+            idle: field(3)?,
+            iowait: field(4)?,
+            irq: field(5)? + field(6)?,
+            steal: field(7)?,
+        })
+    }
+
+    fn total(&self) -> u64 {
+        self.user + self.nice + self.system + self.idle + self.iowait + self.irq + self.steal
+    }
+
+    /// Share of each mode between `earlier` and now, or `None` if no time passed or the
+    /// counters went backwards, e.g. because the CPU was taken offline and back.
+    fn breakdown_since(&self, earlier: &Ticks) -> Option<CpuBreakdown> {
+        let elapsed = self.total().checked_sub(earlier.total())?;
+        if elapsed == 0 {
+            return None;
+        }
+        let percent = |now: u64, then: u64| 100. * now.saturating_sub(then) as f64 / elapsed as f64;
+        Some(CpuBreakdown {
+            user: percent(self.user, earlier.user),
+            nice: percent(self.nice, earlier.nice),
+            system: percent(self.system, earlier.system),
+            iowait: percent(self.iowait, earlier.iowait),
+            irq: percent(self.irq, earlier.irq),
+            steal: percent(self.steal, earlier.steal),
+        })
+    }
+}
+
+/// Reads the time each CPU spent in each mode from `/proc/stat`.
+pub struct ProcStat {
+    path: PathBuf,
+    last: HashMap<String, Ticks>,
+}
+
+impl ProcStat {
+    /// Reads `path` instead of `/proc/stat`, e.g. a test fixture.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ProcStat {
+            path: path.into(),
+            last: HashMap::new(),
+        }
+    }
+
+    /// Breakdown of each CPU since the previous call keyed by name, with "cpu" for all of them
+    /// together and "cpu0", "cpu1" and so on for the cores. Empty on the first call or if the
+    /// file can't be read.
+    pub fn read(&mut self) -> HashMap<String, CpuBreakdown> {
+        let Ok(contents) = fs::read_to_string(&self.path) else {
+            return HashMap::new();
+        };
+        let mut breakdowns = HashMap::new();
+        let mut last = HashMap::new();
+        for line in contents.lines().filter(|line| line.starts_with("cpu")) {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let Some((name, ticks)) = fields
+                .split_first()
+                .and_then(|(name, fields)| Some((*name, Ticks::parse(fields)?)))
+            else {
+                continue;
+            };
+            if let Some(breakdown) = self
+                .last
+                .get(name)
+                .and_then(|earlier| ticks.breakdown_since(earlier))
+            {
+                breakdowns.insert(name.to_owned(), breakdown);
+            }
+            last.insert(name.to_owned(), ticks);
+        }
+        self.last = last;
+        breakdowns
+    }
+}
+
+impl Default for ProcStat {
+    fn default() -> Self {
+        ProcStat::new("/proc/stat")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_proc_stat() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stat");
+        let mut stat = ProcStat::new(&path);
+        assert!(stat.read().is_empty());
+
+        fs::write(
+            &path,
+            "cpu  100 0 100 700 0 0 0 0 0 0\n\
+             cpu0 50 0 50 350 0 0 0 0 0 0\n\
+             cpu1 50 0 50 350 0 0 0 0 0 0\n\
+             intr 12345 0 0\n",
+        )
+        .unwrap();
+        assert!(stat.read().is_empty());
+
+        // cpu0 spends 100 ticks on user, system, I/O wait, interrupts and steal, cpu1 was
+        // unplugged and came back with reset counters.
+        fs::write(
+            &path,
+            "cpu  150 0 120 700 10 5 5 10 0 0\n\
+             cpu0 100 0 70 350 10 5 5 10 0 0\n\
+             cpu1 0 0 0 10 0 0 0 0 0 0\n",
+        )
+        .unwrap();
+        let breakdowns = stat.read();
+        assert_eq!(
+            breakdowns["cpu0"],
+            CpuBreakdown {
+                user: 50.,
+                nice: 0.,
+                system: 20.,
+                iowait: 10.,
+                irq: 10.,
+                steal: 10.,
+            }
+        );
+        assert!(!breakdowns.contains_key("cpu1"));
+        assert_eq!(breakdowns["cpu"].user, 50.);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::topology::cpu_index;
+
+/// Clock speed and throttling of one logical CPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuClock {
+    /// Current clock in MHz, if the CPU has a cpufreq driver.
+    pub frequency: Option<f64>,
+    /// Highest clock the CPU can reach in MHz, including any boost.
+    pub max_frequency: Option<f64>,
+    /// Whether the CPU or its package was throttled for running hot since the previous read.
+    pub throttled: bool,
+}
+
+/// Reads clock speeds from cpufreq and throttling from the `thermal_throttle` counters.
+pub struct CpuFreq {
+    root: PathBuf,
+    /// Throttle events of each CPU as of the previous read.
+    throttle_counts: HashMap<usize, u64>,
+}
+
+impl CpuFreq {
+    /// Reads from `root` instead of `/sys/devices/system/cpu`, e.g. a directory of test fixtures.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        CpuFreq {
+            root: root.into(),
+            throttle_counts: HashMap::new(),
+        }
+    }
+
+    /// Clock of each CPU by index. CPUs without cpufreq or throttle counters, like those of most
+    /// virtual machines, are left out.
+    pub fn read(&mut self) -> HashMap<usize, CpuClock> {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return HashMap::new();
+        };
+        let mut clocks = HashMap::new();
+        let mut throttle_counts = HashMap::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name();
+            let Some(cpu) = name.to_str().and_then(cpu_index) else {
+                continue;
+            };
+            let dir = entry.path();
+            let read = |file: &str| {
+                fs::read_to_string(dir.join(file))
+                    .ok()?
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            };
+            // cpufreq reports kHz.
+            let mhz = |file: &str| read(file).map(|khz| khz as f64 / 1000.);
+            let frequency = mhz("cpufreq/scaling_cur_freq");
+            let max_frequency = mhz("cpufreq/cpuinfo_max_freq");
+            // Package events are counted on every CPU of the package.
+            let throttle_count = match (
+                read("thermal_throttle/core_throttle_count"),
+                read("thermal_throttle/package_throttle_count"),
+            ) {
+                (None, None) => None,
+                (core, package) => Some(core.unwrap_or_default() + package.unwrap_or_default()),
+            };
+            if frequency.is_none() && max_frequency.is_none() && throttle_count.is_none() {
+                continue;
+            }
+            let throttled = throttle_count
+                .zip(self.throttle_counts.get(&cpu))
+                .is_some_and(|(count, &previous)| count > previous);
+            if let Some(count) = throttle_count {
+                throttle_counts.insert(cpu, count);
+            }
+            clocks.insert(
+                cpu,
+                CpuClock {
+                    frequency,
+                    max_frequency,
+                    throttled,
+                },
+            );
+        }
+        self.throttle_counts = throttle_counts;
+        clocks
+    }
+}
+
+impl Default for CpuFreq {
+    fn default() -> Self {
+        CpuFreq::new("/sys/devices/system/cpu")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::write;
+
+    #[test]
+    fn test_read_cpufreq() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "cpu0/cpufreq/scaling_cur_freq", "800000\n");
+        write(root, "cpu0/cpufreq/cpuinfo_max_freq", "4800000\n");
+        write(root, "cpu0/thermal_throttle/core_throttle_count", "3\n");
+        write(root, "cpu0/thermal_throttle/package_throttle_count", "10\n");
+        write(root, "cpu1/cpufreq/scaling_cur_freq", "4200000\n");
+        write(root, "cpu2/topology/core_id", "2\n");
+        write(root, "cpufreq/boost", "1\n");
+        let mut cpufreq = CpuFreq::new(root);
+
+        let clocks = cpufreq.read();
+        assert_eq!(
+            clocks[&0],
+            CpuClock {
+                frequency: Some(800.),
+                max_frequency: Some(4800.),
+                throttled: false,
+            }
+        );
+        assert_eq!(clocks[&1].frequency, Some(4200.));
+        assert_eq!(clocks[&1].max_frequency, None);
+        assert!(!clocks.contains_key(&2));
+
+        // Only an increase since the previous read counts.
+        write(root, "cpu0/thermal_throttle/package_throttle_count", "11\n");
+        assert!(cpufreq.read()[&0].throttled);
+        assert!(!cpufreq.read()[&0].throttled);
+        assert!(CpuFreq::new(root.join("missing")).read().is_empty());
+    }
+}
@@ -0,0 +1,9 @@
+use std::{fs, path::Path};
+
+/// Writes `file` under `root`, creating the directories on the way, to build sysfs or procfs
+/// trees for tests.
+pub fn write(root: &Path, file: &str, contents: &str) {
+    let path = root.join(file);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
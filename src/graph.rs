@@ -0,0 +1,199 @@
+use serde::Deserialize;
+
+/// An auto-scaled axis only shrinks once the data falls below this fraction of it, so the
+/// bars don't jump around every time a burst scrolls out of the window.
+const AUTO_SHRINK_BELOW: f64 = 0.3;
+
+/// How the series of a graph are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// One bar per bucket, with later series painted over earlier ones.
+    #[default]
+    Bars,
+    Line,
+    /// A line smoothed into curves between buckets.
+    Smooth,
+    /// A line with the area below it filled.
+    Area,
+    /// Filled areas with the first series growing down from a center axis and the second up.
+    Mirrored,
+    /// Filled areas with the second series on top of the first.
+    Stacked,
+}
+
+impl Style {
+    pub fn is_filled(self) -> bool {
+        matches!(self, Style::Area | Style::Mirrored | Style::Stacked)
+    }
+}
+
+/// How a graph maps values to bar heights.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scale {
+    /// Each series is scaled to its own maximum.
+    #[default]
+    Independent,
+    /// All series share the largest maximum.
+    Shared,
+    /// Shared, rounded up to a round number and with hysteresis.
+    Auto,
+    /// Always the configured `max`, e.g. the link speed.
+    Fixed,
+    /// Logarithmic up to the configured `max`, or the auto scale if there is none.
+    Log,
+}
+
+impl Scale {
+    /// Whether all series are drawn against one axis that can be labeled.
+    pub fn is_shared(self) -> bool {
+        self != Scale::Independent
+    }
+
+    /// Height of a bar for `value` on an axis topping out at `max`.
+    pub fn height(self, value: f64, max: f64, full_height: f64) -> f64 {
+        let frac = match self {
+            Scale::Log => value.max(0.).ln_1p() / max.ln_1p(),
+            _ => value / max,
+        };
+        frac.clamp(0., 1.) * full_height
+    }
+}
+
+/// Per-graph axis state, which the auto scale needs to remember between frames.
+pub struct Axis {
+    auto_max: f64,
+}
+
+impl Axis {
+    pub fn new() -> Self {
+        Axis { auto_max: 1. }
+    }
+
+    /// Axis maximum for each series given the largest value each one has in view.
+    pub fn maxima<const N: usize>(
+        &mut self,
+        scale: Scale,
+        configured_max: Option<f64>,
+        data_maxima: [f64; N],
+    ) -> [f64; N] {
+        let shared_max = data_maxima.iter().copied().fold(1., f64::max);
+        let max = match (scale, configured_max) {
+            (Scale::Independent, _) => return data_maxima.map(|max| max.max(1.)),
+            (Scale::Shared, _) => shared_max,
+            (Scale::Fixed | Scale::Log, Some(max)) if max > 0. => max,
+            (Scale::Auto | Scale::Fixed | Scale::Log, _) => self.auto(shared_max),
+        };
+        [max; N]
+    }
+
+    fn auto(&mut self, data_max: f64) -> f64 {
+        if data_max > self.auto_max || data_max < self.auto_max * AUTO_SHRINK_BELOW {
+            self.auto_max = nice_ceil(data_max);
+        }
+        self.auto_max
+    }
+}
+
+/// Adds a path through `points` to `ctx`, either as straight segments or as a Catmull-Rom
+/// spline. Control points are clamped to `[min_y, max_y]` so curves don't overshoot the graph.
+pub fn trace(ctx: &cairo::Context, points: &[(f64, f64)], smooth: bool, min_y: f64, max_y: f64) {
+    let Some(&(x, y)) = points.first() else {
+        return;
+    };
+    ctx.line_to(x, y);
+    for (i, &(x, y)) in points.iter().enumerate().skip(1) {
+        if !smooth {
+            ctx.line_to(x, y);
+            continue;
+        }
+        let before = points[i.saturating_sub(2)];
+        let start = points[i - 1];
+        let after = points[(i + 1).min(points.len() - 1)];
+        ctx.curve_to(
+            start.0 + (x - before.0) / 6.,
+            (start.1 + (y - before.1) / 6.).clamp(min_y, max_y),
+            x - (after.0 - start.0) / 6.,
+            (y - (after.1 - start.1) / 6.).clamp(min_y, max_y),
+            x,
+            y,
+        );
+    }
+}
+
+/// Smallest 1, 2 or 5 times a power of ten that is at least `value`.
+fn nice_ceil(value: f64) -> f64 {
+    if value <= 1. {
+        return 1.;
+    }
+    let magnitude = 10f64.powf(value.log10().floor());
+    [1., 2., 5., 10.]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|&nice| nice >= value)
+        .unwrap_or(10. * magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_nice_ceil() {
+        assert_eq!(nice_ceil(0.), 1.);
+        assert_eq!(nice_ceil(1.5), 2.);
+        assert_eq!(nice_ceil(3.), 5.);
+        assert_eq!(nice_ceil(7.), 10.);
+        assert_eq!(nice_ceil(10.), 10.);
+        assert_eq!(nice_ceil(1234.), 2000.);
+        assert_eq!(nice_ceil(600_000.), 1_000_000.);
+    }
+
+    #[test]
+    fn test_axis_maxima() {
+        let mut axis = Axis::new();
+        assert_eq!(axis.maxima(Scale::Independent, None, [10., 0.]), [10., 1.]);
+        assert_eq!(axis.maxima(Scale::Shared, None, [10., 30.]), [30., 30.]);
+        assert_eq!(
+            axis.maxima(Scale::Fixed, Some(125e6), [10., 30.]),
+            [125e6; 2]
+        );
+
+        // The auto scale grows right away but only shrinks once values drop well below it.
+        assert_eq!(axis.maxima(Scale::Auto, None, [300., 120.]), [500.; 2]);
+        assert_eq!(axis.maxima(Scale::Auto, None, [160., 20.]), [500.; 2]);
+        assert_eq!(axis.maxima(Scale::Auto, None, [140., 20.]), [200.; 2]);
+        assert_eq!(axis.maxima(Scale::Fixed, None, [900., 20.]), [1000.; 2]);
+    }
+
+    #[test]
+    fn test_scale_height() {
+        assert_eq!(Scale::Shared.height(5., 10., 30.), 15.);
+        assert_eq!(Scale::Fixed.height(20., 10., 30.), 30.);
+        assert_eq!(Scale::Log.height(0., 1e6, 30.), 0.);
+        assert_eq!(Scale::Log.height(1e6, 1e6, 30.), 30.);
+        assert!((Scale::Log.height(1e3, 1e6, 30.) - 15.).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_parse_graph_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [network]
+            style = "mirrored"
+            scale = "fixed"
+            max = 125000000
+            gridlines = 4
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.disk.scale, Scale::Independent);
+        assert_eq!(config.disk.style, Style::Bars);
+        assert_eq!(config.network.style, Style::Mirrored);
+        assert_eq!(config.network.scale, Scale::Fixed);
+        assert_eq!(config.network.max, Some(125e6));
+        assert_eq!(config.network.gridlines, 4);
+    }
+}
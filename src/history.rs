@@ -0,0 +1,382 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, bail};
+use serde::Deserialize;
+
+/// Resolution and capacity of each tier, finest first. A window is served by the finest tier
+/// that can hold it, so "2m" is drawn per-second, "1h" per-minute and "24h" per-hour.
+const TIERS: [(Duration, usize); 3] = [
+    (Duration::from_secs(1), 300),
+    (Duration::from_secs(60), 180),
+    (Duration::from_secs(60 * 60), 168),
+];
+
+/// Span of the coarsest tier, so the longest window that can be drawn.
+const LONGEST_WINDOW: TimeWindow = {
+    let (resolution, capacity) = TIERS[TIERS.len() - 1];
+    TimeWindow::from_secs(resolution.as_secs() * capacity as u64)
+};
+
+/// Aggregate of all samples that landed in one bucket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bucket {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    count: u32,
+}
+
+impl Bucket {
+    fn single(value: f64) -> Self {
+        Bucket {
+            min: value,
+            avg: value,
+            max: value,
+            count: 1,
+        }
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        let count = self.count + other.count;
+        self.avg = (self.avg * self.count as f64 + other.avg * other.count as f64) / count as f64;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count = count;
+    }
+}
+
+struct Tier {
+    resolution: Duration,
+    capacity: usize,
+    /// Completed buckets, newest first.
+    buckets: VecDeque<Bucket>,
+    /// The bucket currently being filled and the time it started.
+    pending: Option<(Instant, Bucket)>,
+}
+
+impl Tier {
+    fn new(resolution: Duration, capacity: usize) -> Self {
+        Tier {
+            resolution,
+            capacity,
+            buckets: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    /// Adds `bucket` to the pending bucket, returning the previous bucket and its start time if
+    /// `now` is past its end.
+    fn add(&mut self, now: Instant, bucket: Bucket) -> Option<(Instant, Bucket)> {
+        let Some((start, pending)) = &mut self.pending else {
+            self.pending = Some((now, bucket));
+            return None;
+        };
+        let elapsed = now.saturating_duration_since(*start);
+        if elapsed < self.resolution {
+            pending.merge(&bucket);
+            return None;
+        }
+
+        // Keep bucket boundaries aligned to the first sample so jitter doesn't accumulate.
+        let skipped = elapsed.as_nanos() / self.resolution.as_nanos();
+        let new_start = *start + self.resolution * skipped as u32;
+        let completed = (*start, *pending);
+        self.pending = Some((new_start, bucket));
+        push_within_limit(&mut self.buckets, completed.1, self.capacity);
+        Some(completed)
+    }
+
+    /// All buckets, newest first, including the one still being filled.
+    fn iter(&self) -> impl Iterator<Item = &Bucket> {
+        self.pending
+            .iter()
+            .map(|(_, bucket)| bucket)
+            .chain(self.buckets.iter())
+    }
+}
+
+/// Series of samples rolled up into per-second, per-minute and per-hour buckets.
+pub struct History {
+    tiers: Vec<Tier>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            tiers: TIERS
+                .iter()
+                .map(|&(resolution, capacity)| Tier::new(resolution, capacity))
+                .collect(),
+        }
+    }
+
+    /// Keeps only enough buckets to show `window`, for the many series that are only ever drawn
+    /// over one window, like the heatmap's per-core rows.
+    pub fn for_window(window: TimeWindow) -> Self {
+        let mut tiers = Vec::new();
+        for (i, &(resolution, capacity)) in TIERS.iter().enumerate() {
+            if window.0 <= resolution * capacity as u32 || i == TIERS.len() - 1 {
+                let points = (window.0.as_secs_f64() / resolution.as_secs_f64()).ceil();
+                tiers.push(Tier::new(resolution, (points as usize).max(1)));
+                break;
+            }
+            // Finer tiers only roll their samples up into the one serving the window.
+            tiers.push(Tier::new(resolution, 0));
+        }
+        History { tiers }
+    }
+
+    pub fn push(&mut self, now: Instant, value: f64) {
+        let mut sample = (now, Bucket::single(value));
+        for tier in &mut self.tiers {
+            match tier.add(sample.0, sample.1) {
+                Some(completed) => sample = completed,
+                None => break,
+            }
+        }
+    }
+
+    /// The most recent sample pushed, if any.
+    pub fn latest(&self) -> Option<f64> {
+        self.tiers[0].iter().next().map(|bucket| bucket.avg)
+    }
+
+    /// Number of buckets needed to cover `window`, which is how many slots a graph should have.
+    pub fn points(&self, window: TimeWindow) -> usize {
+        let tier = self.tier_for(window);
+        (window.0.as_secs_f64() / tier.resolution.as_secs_f64())
+            .ceil()
+            .max(1.) as usize
+    }
+
+    /// Time covered by each bucket when showing `window`.
+    pub fn resolution(&self, window: TimeWindow) -> Duration {
+        self.tier_for(window).resolution
+    }
+
+    /// Buckets covering `window`, newest first.
+    pub fn window(&self, window: TimeWindow) -> impl Iterator<Item = &Bucket> {
+        self.tier_for(window).iter().take(self.points(window))
+    }
+
+    /// Largest value seen within `window`, or 0 if there are no samples.
+    pub fn window_max(&self, window: TimeWindow) -> f64 {
+        self.window(window)
+            .map(|bucket| bucket.max)
+            .fold(0., f64::max)
+    }
+
+    /// Average of all samples within `window`, or `None` if there are none.
+    pub fn window_avg(&self, window: TimeWindow) -> Option<f64> {
+        let (sum, count) = self.window(window).fold((0., 0), |(sum, count), bucket| {
+            (sum + bucket.avg * bucket.count as f64, count + bucket.count)
+        });
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    /// Largest sum of this series and `other` within `window`, for stacking one on the other.
+    pub fn stacked_window_max(&self, other: &History, window: TimeWindow) -> f64 {
+        self.window(window)
+            .zip(other.window(window))
+            .map(|(a, b)| a.avg + b.avg)
+            .fold(0., f64::max)
+    }
+
+    fn tier_for(&self, window: TimeWindow) -> &Tier {
+        self.tiers
+            .iter()
+            .find(|tier| window.0 <= tier.resolution * tier.capacity as u32)
+            .unwrap_or_else(|| self.tiers.last().unwrap())
+    }
+}
+
+/// How far back a graph looks, written as e.g. "90s", "2m", "1h" or "24h".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeWindow(Duration);
+
+impl TimeWindow {
+    pub const fn from_secs(secs: u64) -> Self {
+        TimeWindow(Duration::from_secs(secs))
+    }
+
+    pub fn duration(self) -> Duration {
+        self.0
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (num, unit) = s.split_at(split);
+        let num: u64 = num
+            .parse()
+            .with_context(|| format!("Invalid time window {s:?}"))?;
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            _ => bail!("Invalid time window unit in {s:?}, expected one of s, m, h, d"),
+        };
+        let window = num
+            .checked_mul(unit_secs)
+            .map(TimeWindow::from_secs)
+            .filter(|&window| window <= LONGEST_WINDOW)
+            .with_context(|| {
+                format!("Time window {s:?} is too long, history only goes back {LONGEST_WINDOW}")
+            })?;
+        if window.0.is_zero() {
+            bail!("Time window must not be empty");
+        }
+        Ok(window)
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+        if secs.is_multiple_of(60 * 60) {
+            write!(f, "{}h", secs / (60 * 60))
+        } else if secs.is_multiple_of(60) {
+            write!(f, "{}m", secs / 60)
+        } else {
+            write!(f, "{secs}s")
+        }
+    }
+}
+
+pub fn push_within_limit<T>(values: &mut VecDeque<T>, new_value: T, limit: usize) {
+    values.push_front(new_value);
+
+    while values.len() > limit {
+        values.pop_back();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools as _;
+
+    use super::*;
+
+    #[test]
+    fn test_push_within_limit() {
+        let mut nums: VecDeque<i32> = Default::default();
+        push_within_limit(&mut nums, 5, 3);
+        assert_eq!(nums.iter().collect_vec(), vec![&5]);
+        push_within_limit(&mut nums, 6, 3);
+        push_within_limit(&mut nums, 7, 3);
+        assert_eq!(nums.iter().collect_vec(), vec![&7, &6, &5]);
+        push_within_limit(&mut nums, 8, 3);
+        assert_eq!(nums.iter().collect_vec(), vec![&8, &7, &6]);
+    }
+
+    #[test]
+    fn test_history_rollups() {
+        let start = Instant::now();
+        let mut history = History::new();
+        for i in 0..=120 {
+            history.push(start + Duration::from_secs(i), i as f64);
+        }
+        assert_eq!(history.latest(), Some(120.));
+
+        let seconds = history.window(TimeWindow::from_secs(120)).collect_vec();
+        assert_eq!(seconds.len(), 120);
+        assert_eq!(seconds[0].avg, 120.);
+        assert_eq!(seconds[119].avg, 1.);
+        assert_eq!(history.window_avg(TimeWindow::from_secs(10)), Some(115.5));
+        assert_eq!(History::new().window_avg(TimeWindow::from_secs(10)), None);
+
+        // Coarser tiers are fed completed buckets from the tier below, so the second currently
+        // being sampled hasn't reached the per-minute tier yet.
+        let minutes = history.window(TimeWindow::from_secs(60 * 60)).collect_vec();
+        assert_eq!(minutes.len(), 2);
+        assert_eq!((minutes[0].min, minutes[0].max), (60., 119.));
+        assert_eq!(minutes[0].avg, 89.5);
+        assert_eq!((minutes[1].min, minutes[1].max), (0., 59.));
+        assert_eq!(minutes[1].avg, 29.5);
+
+        let hours = history
+            .window(TimeWindow::from_secs(24 * 60 * 60))
+            .collect_vec();
+        assert_eq!(hours.len(), 1);
+        assert_eq!((hours[0].min, hours[0].max), (0., 59.));
+    }
+
+    #[test]
+    fn test_history_for_window() {
+        let start = Instant::now();
+        let window = TimeWindow::from_secs(3 * 60);
+        let mut history = History::for_window(window);
+        for i in 0..=600 {
+            history.push(start + Duration::from_secs(i), i as f64);
+        }
+        assert_eq!(history.latest(), Some(600.));
+        assert_eq!(history.points(window), 180);
+        let seconds = history.window(window).collect_vec();
+        assert_eq!(seconds.len(), 180);
+        assert_eq!(seconds[179].avg, 421.);
+
+        // Served per-minute, with the seconds only kept until they're rolled up.
+        let window = TimeWindow::from_secs(10 * 60);
+        let mut history = History::for_window(window);
+        for i in 0..=600 {
+            history.push(start + Duration::from_secs(i), i as f64);
+        }
+        assert_eq!(history.resolution(window), Duration::from_secs(60));
+        assert_eq!(history.window(window).count(), 10);
+        assert_eq!(history.tiers[0].buckets.len(), 0);
+    }
+
+    #[test]
+    fn test_history_points() {
+        let history = History::new();
+        assert_eq!(history.points("2m".parse().unwrap()), 120);
+        assert_eq!(history.points("1h".parse().unwrap()), 60);
+        assert_eq!(history.points("24h".parse().unwrap()), 24);
+        assert_eq!(history.points("90m".parse().unwrap()), 90);
+    }
+
+    #[test]
+    fn test_parse_time_window() {
+        assert_eq!(
+            "50s".parse::<TimeWindow>().unwrap(),
+            TimeWindow::from_secs(50)
+        );
+        assert_eq!(
+            "2m".parse::<TimeWindow>().unwrap(),
+            TimeWindow::from_secs(120)
+        );
+        assert_eq!(
+            "24h".parse::<TimeWindow>().unwrap(),
+            TimeWindow::from_secs(86400)
+        );
+        assert_eq!(TimeWindow::from_secs(7200).to_string(), "2h");
+        assert!("0s".parse::<TimeWindow>().is_err());
+        assert!("2w".parse::<TimeWindow>().is_err());
+        assert!("m".parse::<TimeWindow>().is_err());
+        assert_eq!(
+            "7d".parse::<TimeWindow>().unwrap(),
+            TimeWindow::from_secs(7 * 86400)
+        );
+        assert!("8d".parse::<TimeWindow>().is_err());
+        assert!("999999999999999999d".parse::<TimeWindow>().is_err());
+    }
+}
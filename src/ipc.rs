@@ -0,0 +1,292 @@
+use std::{
+    env, fs,
+    io::{self, BufRead as _, BufReader, Write as _},
+    os::unix::{
+        fs::FileTypeExt as _,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use anyhow::{Context as _, Result, bail};
+use clap::{Subcommand, ValueEnum};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1;
+
+use crate::sample::Sample;
+
+/// A part of the widget that can be shown or hidden on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Panel {
+    Cpu,
+    Disk,
+    Memory,
+    Network,
+    Sensors,
+    Battery,
+    Processes,
+    Heatmap,
+}
+
+/// Layer-shell layer the widget's surface is placed on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+impl From<Layer> for zwlr_layer_shell_v1::Layer {
+    fn from(layer: Layer) -> Self {
+        match layer {
+            Layer::Background => zwlr_layer_shell_v1::Layer::Background,
+            Layer::Bottom => zwlr_layer_shell_v1::Layer::Bottom,
+            Layer::Top => zwlr_layer_shell_v1::Layer::Top,
+            Layer::Overlay => zwlr_layer_shell_v1::Layer::Overlay,
+        }
+    }
+}
+
+/// A command sent to a running widget, as one JSON object per line, e.g.
+/// `{"command":"toggle","panel":"network"}`.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Subcommand)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    /// Stop taking samples, freezing what's drawn.
+    Pause,
+    /// Start taking samples again.
+    Resume,
+    /// Reload the config file even if it hasn't changed.
+    Reload,
+    /// Show or hide a panel.
+    Toggle {
+        #[arg(value_enum)]
+        panel: Panel,
+    },
+    /// Move the widget to another layer.
+    Layer {
+        #[arg(value_enum)]
+        layer: Layer,
+    },
+    /// Draw a new frame right away.
+    Redraw,
+    /// Print the latest sample.
+    Sample,
+}
+
+/// Reply to a request, also one JSON object per line.
+#[derive(Debug, Default, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample: Option<Sample>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response {
+            ok: true,
+            ..Response::default()
+        }
+    }
+
+    pub fn error(error: &anyhow::Error) -> Self {
+        Response {
+            error: Some(format!("{error:#}")),
+            ..Response::default()
+        }
+    }
+
+    pub fn sample(sample: Sample) -> Self {
+        Response {
+            sample: Some(sample),
+            ..Response::ok()
+        }
+    }
+}
+
+/// A request received on the socket, waiting for the main loop to handle it.
+pub struct Command {
+    pub request: Request,
+    reply: mpsc::Sender<Response>,
+}
+
+impl Command {
+    pub fn reply(self, response: Response) {
+        // The client may have hung up already, which is its business.
+        let _ = self.reply.send(response);
+    }
+}
+
+/// Where the control socket of the widget lives.
+pub fn socket_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("widget.sock"))
+}
+
+/// A socket file that is removed again when dropped, so it doesn't outlive the widget.
+pub struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            debug!("Failed to remove {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Binds a unix socket at `path`. A socket left there by a widget that didn't get to clean up
+/// is replaced, but not one that's in use or a file that isn't a socket.
+pub fn bind(path: &Path) -> Result<(UnixListener, SocketFile)> {
+    if UnixStream::connect(path).is_ok() {
+        bail!("Something is already listening on {}", path.display());
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?
+        }
+        Ok(_) => bail!("{} exists and isn't a socket", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to check {}", path.display())),
+    }
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))?;
+    Ok((listener, SocketFile(path.to_owned())))
+}
+
+/// Listens on the control socket at `path` for the rest of the process, passing each request on
+/// to the returned channel. Dropping the returned `SocketFile` only removes the socket file so no
+/// new clients find it; the listener itself keeps running.
+pub fn listen(path: &Path) -> Result<(mpsc::Receiver<Command>, SocketFile)> {
+    let (listener, socket) = bind(path)?;
+    info!("Listening for commands on {}", path.display());
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let sender = sender.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve(stream, sender) {
+                            debug!("Control connection closed: {:#}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to accept control connection: {}", e),
+            }
+        }
+    });
+    Ok((receiver, socket))
+}
+
+fn serve(stream: UnixStream, commands: mpsc::Sender<Command>) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let (reply, response) = mpsc::channel();
+                commands
+                    .send(Command { request, reply })
+                    .context("Widget stopped")?;
+                response.recv().context("Widget dropped the request")?
+            }
+            Err(e) => Response::error(&anyhow::Error::new(e).context("Invalid request")),
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Sends `request` to the widget listening at `path` and returns its reply line.
+pub fn send(path: &Path, request: &Request) -> Result<String> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to the widget at {}", path.display()))?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    if reply.is_empty() {
+        bail!("Widget closed the connection without replying");
+    }
+    Ok(reply.trim_end().to_owned())
+}
+
+/// `widget msg`: sends one request and prints the reply.
+pub fn run_client(request: Request) -> Result<()> {
+    let path = socket_path().context("XDG_RUNTIME_DIR is not set")?;
+    let reply = send(&path, &request)?;
+    println!("{reply}");
+
+    let reply: serde_json::Value = serde_json::from_str(&reply).context("Invalid reply")?;
+    if reply["ok"] != true {
+        bail!("Command failed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let parse = |line| serde_json::from_str::<Request>(line).ok();
+        assert_eq!(parse(r#"{"command":"pause"}"#), Some(Request::Pause));
+        assert_eq!(
+            parse(r#"{"command":"toggle","panel":"network"}"#),
+            Some(Request::Toggle {
+                panel: Panel::Network
+            })
+        );
+        assert_eq!(parse(r#"{"command":"toggle","panel":"gpu"}"#), None);
+        assert_eq!(
+            parse(r#"{"command":"layer","layer":"overlay"}"#),
+            Some(Request::Layer {
+                layer: Layer::Overlay
+            })
+        );
+        assert_eq!(parse(r#"{"command":"explode"}"#), None);
+    }
+
+    #[test]
+    fn test_socket_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("widget.sock");
+        let (commands, socket) = listen(&path).unwrap();
+        assert!(listen(&path).is_err(), "socket is in use");
+
+        let client = {
+            let path = path.clone();
+            thread::spawn(move || send(&path, &Request::Reload))
+        };
+        let command = commands.recv().unwrap();
+        assert_eq!(command.request, Request::Reload);
+        command.reply(Response::error(&anyhow::anyhow!("No config file")));
+        assert_eq!(
+            client.join().unwrap().unwrap(),
+            r#"{"ok":false,"error":"No config file"}"#
+        );
+
+        drop(socket);
+        assert!(!path.exists());
+        // Only stale sockets are replaced.
+        fs::write(&path, "").unwrap();
+        assert!(listen(&path).is_err());
+        assert!(path.exists());
+    }
+}
@@ -1,19 +1,47 @@
+mod alert;
+mod animation;
+mod bar;
+mod cli;
+mod colormap;
+mod config;
+mod cores;
+mod cpu_time;
+mod cpufreq;
+#[cfg(test)]
+mod fixture;
+mod graph;
+mod history;
+mod ipc;
+mod meminfo;
+mod metrics;
+mod power;
+mod processes;
+mod rate;
+mod sample;
+mod sensors;
+mod stream;
+mod threshold;
+mod topology;
+mod units;
+
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, HashSet},
     f64::consts::PI,
+    fs::{self, File},
     os::unix::io::{AsRawFd, BorrowedFd},
-    path::Path,
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError},
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context as _, Result};
+use anyhow::{Context as _, Result, anyhow};
 use cairo::{FontSlant, FontWeight, Format, ImageSurface, LinearGradient};
+use clap::Parser as _;
 use itertools::Itertools as _;
 use log::{debug, error, info};
-use sysinfo::{Disk, Disks, Networks, System};
 use wayland_client::{
-    Connection, Dispatch, QueueHandle,
+    Connection, Dispatch, Proxy as _, QueueHandle,
     protocol::{
         wl_buffer, wl_callback, wl_compositor, wl_output, wl_registry, wl_shm, wl_shm_pool,
         wl_surface,
@@ -22,16 +50,63 @@ use wayland_client::{
 use wayland_protocols::xdg::shell::client::xdg_wm_base;
 use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 
-// const RENDER_INTERVAL: Duration = Duration::from_millis(100);
-const RENDER_INTERVAL: Duration = Duration::from_secs(1);
-const MAX_CPU_USAGE_POINTS: usize = 50;
+use crate::{
+    alert::{AlertEngine, Metric},
+    animation::Animator,
+    cli::{Cli, Command, RunArgs, SnapshotArgs},
+    config::{Config, GraphConfig, ThresholdsConfig},
+    cores::{grid_shape, group_cores},
+    cpu_time::CpuBreakdown,
+    graph::{Axis, Style},
+    history::{History, TimeWindow},
+    ipc::{Panel, Request, Response},
+    meminfo::MemoryBreakdown,
+    metrics::SharedMetrics,
+    power::ChargeState,
+    processes::ProcessSample,
+    sample::{CoreSample, Sample, Sampler, Usage},
+    sensors::SensorKind,
+    threshold::Level,
+    topology::CpuKind,
+    units::{DataUnit, UnitFormat},
+};
+
+const GAUGE_RADIUS: f64 = 100.;
 const GAUGE_UPWARD_SHIFT: f64 = 20.;
 const PILL_MARGIN: f64 = 20.;
 const PILL_LENGTH: f64 = 175.;
-const MAX_GRAPH_POINTS: usize = 150;
 const GRAPH_LENGTH: f64 = 175.;
 const GRAPH_HEIGHT: f64 = 30.;
-const GRAPH_BAR_WIDTH: f64 = GRAPH_LENGTH / MAX_GRAPH_POINTS as f64;
+const SENSOR_ROWS: usize = 8;
+const SENSOR_ROW_HEIGHT: f64 = 12.;
+const SPARKLINE_LENGTH: f64 = 50.;
+const HEATMAP_LENGTH: f64 = 200.;
+const HEATMAP_HEIGHT: f64 = 48.;
+const CORE_CELL: f64 = 10.;
+const CORE_GAP: f64 = 2.;
+/// Longest wait for a control command while paused, before Wayland events get handled.
+const PAUSED_WAKEUP: Duration = Duration::from_secs(1);
+/// How long the throttling indicator stays up after a core was last throttled.
+const THROTTLE_HOLD: Duration = Duration::from_secs(30);
+const PINK: (f64, f64, f64) = (212. / 255., 79. / 255., 126. / 255.);
+const GREEN: (f64, f64, f64) = (94. / 255., 1., 108. / 255.);
+/// Usage of E-cores, to tell them from P-cores on hybrid CPUs.
+const BLUE: (f64, f64, f64) = (90. / 255., 190. / 255., 1.);
+/// Colors of user, nice, system, I/O wait, interrupt and steal time, in `CpuBreakdown::MODES`
+/// order.
+const MODE_COLORS: [(f64, f64, f64); 6] = [
+    PINK,
+    (240. / 255., 160. / 255., 190. / 255.),
+    (208. / 255., 143. / 255., 1.),
+    BLUE,
+    GREEN,
+    (1., 140. / 255., 0.),
+];
+/// Colors of the memory pill's segments, in `MemoryBreakdown::SEGMENTS` order.
+const MEMORY_COLORS: [(f64, f64, f64); 5] =
+    [PINK, MODE_COLORS[2], BLUE, MODE_COLORS[1], MODE_COLORS[5]];
+/// Swap that is compressed in RAM rather than written to disk.
+const PALE_GREEN: (f64, f64, f64) = (190. / 255., 1., 200. / 255.);
 
 struct BufferResources {
     pool: wl_shm_pool::WlShmPool,
@@ -44,6 +119,16 @@ struct BufferResources {
     size: usize,
 }
 
+/// A `wl_output` and what the compositor has told us about it.
+struct Output {
+    output: wl_output::WlOutput,
+    name: Option<String>,
+    description: Option<String>,
+    /// Current mode in pixels.
+    size: Option<(i32, i32)>,
+    scale: i32,
+}
+
 struct App {
     compositor: Option<wl_compositor::WlCompositor>,
     layer_shell: Option<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
@@ -52,25 +137,55 @@ struct App {
     buffer_resources: Option<BufferResources>,
     surface: Option<wl_surface::WlSurface>,
     layer_surface: Option<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1>,
-    outputs: Vec<wl_output::WlOutput>,
+    outputs: Vec<Output>,
+    /// The output picked with `--output`, which the scale factor is taken from.
+    selected_output: Option<wl_output::WlOutput>,
     width: u32,
     height: u32,
     scale_factor: i32,
-    system: System,
-    disks: Disks,
-    networks: Networks,
-    cpu_usage_points: VecDeque<f64>,
-    read_bytes_points: VecDeque<u64>,
-    written_bytes_points: VecDeque<u64>,
-    downloaded_bytes_points: VecDeque<u64>,
-    uploaded_bytes_points: VecDeque<u64>,
+    sampler: Sampler,
+    sample: Sample,
+    config: Config,
+    config_path: Option<PathBuf>,
+    config_mtime: Option<SystemTime>,
+    interval: Duration,
+    cpu_usage: History,
+    /// 1-minute load average as a percentage of the number of CPUs.
+    load: History,
+    /// Share of all cores' time in each of `CpuBreakdown::MODES`.
+    cpu_modes: [History; 6],
+    /// Usage of each core as grouped for the gauge, over the heatmap's window.
+    core_history: HashMap<String, History>,
+    /// When a core was last throttled, since it rarely lasts long enough to notice.
+    throttled_at: Option<Instant>,
+    /// Bytes per second, summed over all disks or interfaces.
+    read_rate: History,
+    write_rate: History,
+    download_rate: History,
+    upload_rate: History,
+    /// Readings of each hwmon sensor, by label.
+    sensor_history: HashMap<String, History>,
+    /// Watts into or out of the batteries since they last started or stopped charging.
+    battery_power: History,
+    disk_axis: Axis,
+    network_axis: Axis,
+    animator: Animator,
+    next_sample: Instant,
+    alert_engine: AlertEngine,
+    /// Requests from the control socket, if it could be set up.
+    commands: Option<mpsc::Receiver<ipc::Command>>,
+    paused: bool,
+    /// Set once the compositor takes the layer surface away, which ends the widget.
+    closed: bool,
+    hidden_panels: HashSet<Panel>,
+    /// Shared with the Prometheus listener, if there is one.
+    metrics: Option<SharedMetrics>,
 }
 
 impl App {
-    fn new() -> Self {
-        let system = System::new();
-        let disks = Disks::new();
-        let networks = Networks::new();
+    fn new(config_path: Option<PathBuf>, interval: Duration) -> Self {
+        let config = Config::default();
+        let animator = Animator::new(config.animation.duration(), config.animation.easing);
 
         let mut this = App {
             compositor: None,
@@ -81,76 +196,261 @@ impl App {
             surface: None,
             layer_surface: None,
             outputs: Vec::new(),
+            selected_output: None,
             width: 0,        // Will be updated by layer surface configure event
             height: 0,       // Will be updated by layer surface configure event
             scale_factor: 1, // Will be updated from output events
-            system,
-            disks,
-            networks,
-            cpu_usage_points: Default::default(),
-            read_bytes_points: Default::default(),
-            written_bytes_points: Default::default(),
-            downloaded_bytes_points: Default::default(),
-            uploaded_bytes_points: Default::default(),
+            sampler: Sampler::new(),
+            sample: Sample::default(),
+            config,
+            config_path: config_path.or_else(Config::default_path),
+            config_mtime: None,
+            interval,
+            cpu_usage: History::new(),
+            load: History::new(),
+            cpu_modes: std::array::from_fn(|_| History::new()),
+            core_history: HashMap::new(),
+            throttled_at: None,
+            read_rate: History::new(),
+            write_rate: History::new(),
+            download_rate: History::new(),
+            upload_rate: History::new(),
+            sensor_history: HashMap::new(),
+            battery_power: History::new(),
+            disk_axis: Axis::new(),
+            network_axis: Axis::new(),
+            animator,
+            next_sample: Instant::now(),
+            alert_engine: AlertEngine::new(),
+            commands: None,
+            paused: false,
+            closed: false,
+            hidden_panels: HashSet::new(),
+            metrics: None,
         };
+        this.reload_config_if_changed();
         this.refresh_system();
         this
     }
 
-    fn refresh_system(&mut self) {
-        self.system.refresh_cpu_all();
-        self.system.refresh_memory();
-        self.disks.refresh(true /*remove_not_listed_disks*/);
-        self.networks.refresh(true /*remove_not_listed_interfaces*/);
-
-        // Calculate average CPU usage across all cores
-        let cpus = self.system.cpus();
-        if cpus.is_empty() {
-            panic!("CPUs cannot be empty");
+    /// Picks up edits to the config file so graph windows can be switched without a restart.
+    fn reload_config_if_changed(&mut self) {
+        let Some(path) = &self.config_path else {
+            return;
+        };
+        let mtime = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if mtime == self.config_mtime {
+            return;
+        }
+        self.config_mtime = mtime;
+        if let Err(e) = self.reload_config() {
+            error!("Keeping previous config: {:#}", e);
         }
-        let total_usage: f32 = cpus.iter().map(|cpu| cpu.cpu_usage()).sum();
-        let cpu_usage = (total_usage / cpus.len() as f32).min(100.) as f64;
-        push_within_limit(&mut self.cpu_usage_points, cpu_usage, MAX_CPU_USAGE_POINTS);
+    }
 
-        let read_bytes = self
-            .disks
-            .iter()
-            .map(|disk| disk.usage().read_bytes)
-            .sum::<u64>();
-        push_within_limit(&mut self.read_bytes_points, read_bytes, MAX_GRAPH_POINTS);
+    fn reload_config(&mut self) -> Result<()> {
+        let path = self.config_path.as_ref().context("No config path")?;
+        let config = Config::load(path)?;
+        info!("Loaded config from {}", path.display());
+        self.animator
+            .configure(config.animation.duration(), config.animation.easing);
+        self.sampler.configure(&config);
+        if config.heatmap.window != self.config.heatmap.window {
+            self.core_history.clear();
+        }
+        self.config = config;
+        Ok(())
+    }
 
-        let written_bytes = self
-            .disks
-            .iter()
-            .map(|disk| disk.usage().written_bytes)
-            .sum::<u64>();
-        push_within_limit(
-            &mut self.written_bytes_points,
-            written_bytes,
-            MAX_GRAPH_POINTS,
+    fn refresh_system(&mut self) {
+        let now = Instant::now();
+        self.next_sample = now + self.interval;
+        self.animator.sample_taken(now, self.interval);
+
+        let sample = self.sampler.sample(now);
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .lock()
+                .unwrap()
+                .record_sample(&sample, now.elapsed());
+        }
+        if let Some(cpu) = sample.cpu {
+            self.cpu_usage.push(now, cpu);
+            // Kept in step with `cpu_usage` even without a breakdown, so the rings line up.
+            let modes = sample.cpu_breakdown.unwrap_or_default().values();
+            for (history, percent) in self.cpu_modes.iter_mut().zip(modes) {
+                history.push(now, percent);
+            }
+        }
+        if let Some(load) = sample.load {
+            self.load.push(now, load.percent_of(sample.cores.len()).one);
+        }
+        // Rows of cores that went offline, or of the grouping before a config change, are dropped.
+        let cores = group_cores(
+            &sample.cores,
+            self.sampler.topology(),
+            self.config.cpu.group,
         );
+        self.core_history
+            .retain(|name, _| cores.iter().any(|core| &core.name == name));
+        for core in &cores {
+            self.core_history
+                .entry(core.name.clone())
+                .or_insert_with(|| History::for_window(self.config.heatmap.window))
+                .push(now, core.usage);
+        }
+        if sample.cores.iter().any(|core| core.throttled) {
+            self.throttled_at = Some(now);
+        }
+        // Rates that can't be measured aren't recorded as 0.
+        for (history, rate) in [
+            (&mut self.read_rate, sample.disk_read),
+            (&mut self.write_rate, sample.disk_write),
+            (&mut self.download_rate, sample.download),
+            (&mut self.upload_rate, sample.upload),
+        ] {
+            if let Some(rate) = rate {
+                history.push(now, rate);
+            }
+        }
+        // Sensors of unplugged devices are forgotten rather than kept around as flat lines.
+        self.sensor_history
+            .retain(|label, _| sample.sensors.iter().any(|r| &r.label == label));
+        for reading in &sample.sensors {
+            self.sensor_history
+                .entry(reading.label.clone())
+                .or_insert_with(History::new)
+                .push(now, reading.value);
+        }
+        // Power draw while charging says nothing about the time to empty, so the average starts
+        // over whenever the state changes.
+        let state = |sample: &Sample| sample.battery.as_ref().map(|battery| battery.state);
+        if state(&sample) != state(&self.sample) {
+            self.battery_power = History::new();
+        }
+        if let Some(power) = sample.battery.as_ref().and_then(|battery| battery.power) {
+            self.battery_power.push(now, power);
+        }
+        self.sample = sample;
 
-        let downloaded_bytes = self
-            .networks
-            .iter()
-            .map(|(_if_name, network)| network.received())
-            .sum::<u64>();
-        push_within_limit(
-            &mut self.downloaded_bytes_points,
-            downloaded_bytes,
-            MAX_GRAPH_POINTS,
-        );
+        self.evaluate_alerts(now);
+    }
 
-        let uploaded_bytes = self
-            .networks
+    fn evaluate_alerts(&mut self, now: Instant) {
+        let values = self
+            .config
+            .alerts
             .iter()
-            .map(|(_if_name, network)| network.transmitted())
-            .sum::<u64>();
-        push_within_limit(
-            &mut self.uploaded_bytes_points,
-            uploaded_bytes,
-            MAX_GRAPH_POINTS,
-        );
+            .map(|rule| self.metric_value(&rule.metric))
+            .collect_vec();
+        let fired = self
+            .alert_engine
+            .evaluate(&self.config.alerts, &values, now);
+        for i in fired {
+            let rule = &self.config.alerts[i];
+            let value = values[i].unwrap_or_default();
+            let value = if rule.metric.is_rate() {
+                let unit = match rule.metric {
+                    Metric::Download | Metric::Upload => self.config.units.network,
+                    _ => self.config.units.disk,
+                };
+                self.config.units.format(unit).rate(value)
+            } else {
+                format!("{value:.1}%")
+            };
+            alert::dispatch(rule, format!("{} is at {}", rule.metric, value));
+        }
+    }
+
+    /// Latest value of `metric`, if it is currently available.
+    fn metric_value(&self, metric: &Metric) -> Option<f64> {
+        match metric {
+            Metric::Cpu => self.sample.cpu,
+            Metric::Memory => self.sample.memory.and_then(|memory| memory.percent()),
+            Metric::Swap => self.sample.swap.and_then(|swap| swap.percent()),
+            Metric::Disk(mount_point) => self
+                .sample
+                .disk(mount_point)
+                .and_then(|disk| disk.space.percent()),
+            Metric::DiskRead => self.sample.disk_read,
+            Metric::DiskWrite => self.sample.disk_write,
+            Metric::Download => self.sample.download,
+            Metric::Upload => self.sample.upload,
+        }
+    }
+
+    /// Idles until the next sample is due. A control command cuts this short, so its effect is
+    /// drawn right away.
+    fn idle(&mut self) {
+        let timeout = self.next_sample.saturating_duration_since(Instant::now());
+        let command = match &self.commands {
+            None => {
+                thread::sleep(timeout);
+                return;
+            }
+            // Nothing changes while paused, but this runs in the frame callback, so it still
+            // has to return now and then for Wayland events to be handled.
+            Some(commands) if self.paused => commands.recv_timeout(PAUSED_WAKEUP),
+            Some(commands) => commands.recv_timeout(timeout),
+        };
+        match command {
+            Ok(command) => self.handle_command(command),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                error!("Control socket stopped listening");
+                self.commands = None;
+            }
+        }
+    }
+
+    fn handle_pending_commands(&mut self) {
+        while let Some(command) = self
+            .commands
+            .as_ref()
+            .and_then(|commands| commands.try_recv().ok())
+        {
+            self.handle_command(command);
+        }
+    }
+
+    fn handle_command(&mut self, command: ipc::Command) {
+        info!("Control command: {:?}", command.request);
+        let response = match &command.request {
+            Request::Pause => {
+                self.paused = true;
+                Response::ok()
+            }
+            Request::Resume => {
+                self.paused = false;
+                Response::ok()
+            }
+            Request::Reload => match self.reload_config() {
+                Ok(()) => Response::ok(),
+                Err(e) => Response::error(&e),
+            },
+            Request::Toggle { panel } => {
+                if !self.hidden_panels.remove(panel) {
+                    self.hidden_panels.insert(*panel);
+                }
+                Response::ok()
+            }
+            Request::Layer { layer } => match (&self.layer_surface, &self.surface) {
+                // Changing the layer of an existing surface came with version 2.
+                (Some(layer_surface), Some(_)) if layer_surface.version() < 2 => {
+                    Response::error(&anyhow!("The compositor can't change layers"))
+                }
+                (Some(layer_surface), Some(surface)) => {
+                    layer_surface.set_layer((*layer).into());
+                    surface.commit();
+                    Response::ok()
+                }
+                _ => Response::error(&anyhow!("No layer surface")),
+            },
+            // Every command wakes the frame loop, which draws a fresh frame right after.
+            Request::Redraw => Response::ok(),
+            Request::Sample => Response::sample(self.sample.clone()),
+        };
+        command.reply(response);
     }
 
     fn render(&mut self, qhandle: &QueueHandle<Self>) -> Result<()> {
@@ -164,30 +464,19 @@ impl App {
             return Ok(());
         }
 
-        self.refresh_system();
+        // Frames can come faster than samples while animating, so only sample when one is due.
+        self.handle_pending_commands();
+        self.reload_config_if_changed();
+        let now = Instant::now();
+        if !self.paused && now >= self.next_sample {
+            self.refresh_system();
+        }
+        self.animator.begin_frame(now);
+        let render_start = Instant::now();
 
-        // Create a Cairo surface scaled for high-DPI
         let physical_width = (self.width as i32) * self.scale_factor;
         let physical_height = (self.height as i32) * self.scale_factor;
-        let mut cairo_surface =
-            ImageSurface::create(Format::ARgb32, physical_width, physical_height)
-                .context("Failed to create Cairo surface")?;
-        let cairo_ctx =
-            cairo::Context::new(&cairo_surface).context("Failed to create Cairo context")?;
-
-        // Scale the Cairo context to work in logical coordinates
-        cairo_ctx.scale(self.scale_factor as f64, self.scale_factor as f64);
-
-        // Clear the background (transparent)
-        cairo_ctx.set_source_rgba(0., 0., 0., 0.);
-        cairo_ctx.set_operator(cairo::Operator::Source);
-        cairo_ctx.paint().context("Failed to paint")?;
-        cairo_ctx.set_operator(cairo::Operator::Over);
-
-        self.draw_main(&cairo_ctx).context("Error in draw_main")?;
-
-        // Drop the Cairo context to release the surface
-        drop(cairo_ctx);
+        let mut cairo_surface = self.draw_frame()?;
 
         // Get the surface data
         let data = cairo_surface
@@ -209,7 +498,7 @@ impl App {
         if self
             .buffer_resources
             .as_ref()
-            .map_or(true, |b| b.size != size)
+            .is_none_or(|b| b.size != size)
         {
             let old_size = self.buffer_resources.as_ref().map_or(0, |b| b.size);
             info!(
@@ -283,23 +572,98 @@ impl App {
         surface.attach(Some(&buffer), 0, 0);
         surface.commit();
 
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .lock()
+                .unwrap()
+                .record_render(render_start.elapsed());
+        }
         debug!("Render completed successfully");
         Ok(())
     }
 
+    /// Draws the widget into a new image at the output's scale.
+    fn draw_frame(&mut self) -> Result<ImageSurface> {
+        // Create a Cairo surface scaled for high-DPI
+        let physical_width = (self.width as i32) * self.scale_factor;
+        let physical_height = (self.height as i32) * self.scale_factor;
+        let cairo_surface = ImageSurface::create(Format::ARgb32, physical_width, physical_height)
+            .context("Failed to create Cairo surface")?;
+        let cairo_ctx =
+            cairo::Context::new(&cairo_surface).context("Failed to create Cairo context")?;
+
+        // Scale the Cairo context to work in logical coordinates
+        cairo_ctx.scale(self.scale_factor as f64, self.scale_factor as f64);
+
+        // Clear the background (transparent)
+        cairo_ctx.set_source_rgba(0., 0., 0., 0.);
+        cairo_ctx.set_operator(cairo::Operator::Source);
+        cairo_ctx.paint().context("Failed to paint")?;
+        cairo_ctx.set_operator(cairo::Operator::Over);
+
+        self.draw_main(&cairo_ctx).context("Error in draw_main")?;
+        Ok(cairo_surface)
+    }
+
     fn draw_main(&mut self, ctx: &cairo::Context) -> Result<()> {
-        // Draw a circle with radial gradient at the bottom center
-        let gauge_radius = 100.;
-        let gauge_center_x = self.width as f64 / 2.;
-        let gauge_center_y = self.height as f64 - GAUGE_UPWARD_SHIFT;
+        ctx.select_font_face("Inconsolata Nerd Font", FontSlant::Normal, FontWeight::Bold);
+        if self.is_visible(Panel::Cpu) {
+            self.draw_cpu(ctx).context("Error drawing CPU panel")?;
+        }
+        if self.config.heatmap.enabled && self.is_visible(Panel::Heatmap) {
+            self.draw_heatmap(ctx)
+                .context("Error drawing heatmap panel")?;
+        }
+        if self.is_visible(Panel::Disk) {
+            self.draw_disk(ctx).context("Error drawing disk panel")?;
+        }
+        if self.is_visible(Panel::Memory) {
+            self.draw_memory(ctx)
+                .context("Error drawing memory panel")?;
+        }
+        if self.is_visible(Panel::Network) {
+            self.draw_network(ctx)
+                .context("Error drawing network panel")?;
+        }
+        if self.is_visible(Panel::Battery) {
+            self.draw_battery(ctx)
+                .context("Error drawing battery panel")?;
+        }
+        if self.is_visible(Panel::Processes) {
+            self.draw_processes(ctx)
+                .context("Error drawing processes panel")?;
+        }
+        if self.is_visible(Panel::Sensors) {
+            self.draw_sensors(ctx)
+                .context("Error drawing sensors panel")?;
+        }
+        Ok(())
+    }
+
+    fn is_visible(&self, panel: Panel) -> bool {
+        !self.hidden_panels.contains(&panel)
+    }
+
+    /// Center of the CPU gauge, which the other panels are laid out around.
+    fn gauge_center(&self) -> (f64, f64) {
+        (
+            self.width as f64 / 2.,
+            self.height as f64 - GAUGE_UPWARD_SHIFT,
+        )
+    }
 
+    /// The gauge at the bottom center with an arc per core and the usage history around it.
+    fn draw_cpu(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+
+        // Draw a circle with radial gradient at the bottom center
         let pattern = cairo::RadialGradient::new(
             gauge_center_x,
             gauge_center_y,
             0., // Inner circle (center, radius)
             gauge_center_x,
             gauge_center_y,
-            gauge_radius, // Outer circle (center, radius)
+            GAUGE_RADIUS, // Outer circle (center, radius)
         );
 
         pattern.add_color_stop_rgba(0., 0., 0., 0., 0.);
@@ -307,7 +671,7 @@ impl App {
         pattern.add_color_stop_rgba(1., 208. / 255., 143. / 255., 1., 0.25);
 
         ctx.set_source(&pattern).context("Error setting pattern")?;
-        ctx.arc(gauge_center_x, gauge_center_y, gauge_radius, 0., 2. * PI);
+        ctx.arc(gauge_center_x, gauge_center_y, GAUGE_RADIUS, 0., 2. * PI);
         ctx.fill()?;
 
         // Draw a border around it
@@ -316,119 +680,353 @@ impl App {
         ctx.arc(
             gauge_center_x,
             gauge_center_y,
-            gauge_radius + 4.,
+            GAUGE_RADIUS + 4.,
             0.,
             2. * PI,
         );
         ctx.stroke()?;
 
-        let cpus = self.system.cpus();
+        let cores = group_cores(
+            &self.sample.cores,
+            self.sampler.topology(),
+            self.config.cpu.group,
+        );
+        if self.config.cpu.layout.rings(cores.len()) {
+            self.draw_core_rings(&cores, ctx)?;
+        } else {
+            self.draw_core_grid(&cores, ctx)?;
+        }
 
-        ctx.set_source_rgb(212. / 255., 79. / 255., 126. / 255.);
-        ctx.set_line_width(4.);
-        let top = 3. * PI / 2.;
-        for (i, mut cpu_pair) in cpus.iter().chunks(2).into_iter().enumerate() {
-            let Some(cpu1) = cpu_pair.next() else {
-                continue;
-            };
-            let radius = gauge_radius - (i as f64) * 4. - 2.;
-            ctx.arc(
-                gauge_center_x,
-                gauge_center_y,
-                radius,
-                top,
-                top + (cpu1.cpu_usage() as f64) / 100. * PI / 2.,
+        // Display the overall usage in the middle of the gauge
+        ctx.set_source_rgba(1., 1., 1., 0.6);
+        ctx.set_font_size(16.);
+
+        let text = match self.sample.cpu {
+            Some(cpu_usage) => {
+                let cpu_usage = self.animator.value("cpu", cpu_usage);
+                self.config
+                    .thresholds
+                    .cpu
+                    .level(cpu_usage)
+                    .label(&format!("{cpu_usage:.1}%"))
+            }
+            None => "n/a".to_owned(),
+        };
+        let x = self.width as f64 / 2.;
+        let y = self.height as f64 - 12.;
+        self.text_centered_at(&text, x, y, 16., ctx)?;
+        self.text_centered_at(" ", x, y - 24., 32., ctx)?;
+        if self
+            .throttled_at
+            .is_some_and(|throttled_at| throttled_at.elapsed() < THROTTLE_HOLD)
+        {
+            let (red, green, blue) = Level::Warning.color(PINK);
+            ctx.set_source_rgb(red, green, blue);
+            self.text_centered_at("\u{f2c9}", x + 26., y - 30., 14., ctx)?;
+        }
+        self.draw_load(x, y - 52., ctx)?;
+        ctx.new_path();
+
+        let cpu_window = self.config.cpu.window;
+        let arc_step = PI / self.cpu_usage.points(cpu_window) as f64;
+        let scroll = self.scroll_offset(&self.cpu_usage, cpu_window);
+        let modes = (self.config.cpu.breakdown && self.sample.cpu_breakdown.is_some()).then(|| {
+            self.cpu_modes
+                .iter()
+                .map(|history| {
+                    history
+                        .window(cpu_window)
+                        .map(|bucket| bucket.avg)
+                        .collect_vec()
+                })
+                .collect_vec()
+        });
+        for (i, cpu_usage) in self.cpu_usage.window(cpu_window).enumerate() {
+            let level = self.config.thresholds.cpu.level(cpu_usage.avg);
+            let (start, end) = (
+                -arc_step * (i as f64 - scroll),
+                -arc_step * (i as f64 - scroll + 1.),
             );
-            ctx.stroke()?;
 
-            if let Some(cpu2) = cpu_pair.next() {
+            // Modes are stacked outward from the gauge, unless the usage is past a threshold.
+            let segments = match &modes {
+                Some(modes) if level == Level::Normal => modes
+                    .iter()
+                    .zip(MODE_COLORS)
+                    .map(|(mode, color)| (mode.get(i).copied().unwrap_or_default(), color))
+                    .collect_vec(),
+                _ => vec![(cpu_usage.avg, level.color(PINK))],
+            };
+            let mut radius = GAUGE_RADIUS + 6.;
+            for (percent, (red, green, blue)) in segments {
+                let line_width = percent / 5.;
+                ctx.set_line_width(line_width);
                 ctx.arc_negative(
                     gauge_center_x,
                     gauge_center_y,
-                    radius,
-                    top,
-                    top - (cpu2.cpu_usage() as f64) / 100. * PI / 2.,
+                    radius + line_width / 2.,
+                    start,
+                    end,
                 );
+                ctx.set_source_rgb(red, green, blue);
                 ctx.stroke()?;
+                radius += line_width;
             }
         }
 
-        // Display the load average below the arc
-        ctx.set_source_rgba(1., 1., 1., 0.6);
-        ctx.select_font_face("Inconsolata Nerd Font", FontSlant::Normal, FontWeight::Bold);
-        ctx.set_font_size(16.);
+        Ok(())
+    }
 
-        let text = format!("{:.1}%", self.cpu_usage_points.front().unwrap());
-        let x = self.width as f64 / 2.;
-        let y = self.height as f64 - 12.;
-        self.text_centered_at(&text, x, y, 16., ctx)?;
-        self.text_centered_at(" ", x, y - 24., 32., ctx)?;
-        ctx.new_path();
+    /// An arc per core inside the gauge, with two cores sharing each ring from the top. Cores
+    /// come ordered by topology, so SMT siblings share a ring and E-cores are drawn after P-cores.
+    fn draw_core_rings(&mut self, cores: &[CoreSample], ctx: &cairo::Context) -> Result<()> {
+        let center = self.gauge_center();
+        let mut style = RingStyle {
+            animator: &mut self.animator,
+            thresholds: &self.config.thresholds,
+            breakdown: self.config.cpu.breakdown,
+        };
+        let frequency = self.config.cpu.frequency;
+
+        // Rings get thinner rather than reaching into the text if more are forced into the gauge.
+        let rings = cores.len().div_ceil(2);
+        let ring_width = (GAUGE_RADIUS * 0.4 / rings as f64).min(4.);
+        // The clock takes the inner third of each ring.
+        let (usage_offset, usage_width) = if frequency {
+            (ring_width / 6., ring_width * 2. / 3.)
+        } else {
+            (0., ring_width)
+        };
+        for (i, pair) in cores.iter().chunks(2).into_iter().enumerate() {
+            let radius = GAUGE_RADIUS - (i as f64 + 0.5) * ring_width;
+            for (core, direction) in pair.zip([1., -1.]) {
+                let arc = CoreArc {
+                    center,
+                    radius,
+                    direction,
+                };
+                ctx.set_line_width(usage_width);
+                core_arc(ctx, &mut style, core, arc.offset(usage_offset))?;
+                if frequency {
+                    ctx.set_line_width(ring_width / 3.);
+                    frequency_arc(ctx, style.animator, core, arc.offset(-ring_width / 3.))?;
+                }
+            }
+        }
+        Ok(())
+    }
 
-        let arc_step = PI / MAX_CPU_USAGE_POINTS as f64;
-        for (i, cpu_usage) in self.cpu_usage_points.iter().enumerate() {
-            let line_width = *cpu_usage / 5.;
-            ctx.set_line_width(line_width);
-            ctx.arc_negative(
-                gauge_center_x,
-                gauge_center_y,
-                gauge_radius + 6. + line_width / 2.,
-                -arc_step * i as f64,
-                -arc_step * i as f64 - arc_step,
-            );
-            ctx.set_source_rgb(212. / 255., 79. / 255., 126. / 255.);
+    /// A cell per core above the gauge and its history ring, filled more the busier the core.
+    fn draw_core_grid(&mut self, cores: &[CoreSample], ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let (columns, rows) = grid_shape(cores.len(), 4.);
+        let left = gauge_center_x - (columns as f64 * (CORE_CELL + CORE_GAP) - CORE_GAP) / 2.;
+        let top = gauge_center_y - GAUGE_RADIUS - 34. - rows as f64 * (CORE_CELL + CORE_GAP);
+
+        ctx.set_line_width(1.);
+        for (i, core) in cores.iter().enumerate() {
+            let x = left + (i % columns) as f64 * (CORE_CELL + CORE_GAP);
+            let y = top + (i / columns) as f64 * (CORE_CELL + CORE_GAP);
+            let usage = self.animator.value(&core.name, core.usage);
+            let (red, green, blue) = self
+                .config
+                .thresholds
+                .cpu
+                .level(usage)
+                .color(core_color(core));
+            ctx.set_source_rgba(red, green, blue, 0.1 + 0.9 * (usage / 100.).clamp(0., 1.));
+            ctx.rectangle(x, y, CORE_CELL, CORE_CELL);
+            ctx.fill()?;
+            ctx.set_source_rgba(1., 1., 1., 0.2);
+            ctx.rectangle(x + 0.5, y + 0.5, CORE_CELL - 1., CORE_CELL - 1.);
             ctx.stroke()?;
+
+            // The clock is a bar along the bottom of the cell.
+            if let Some(fraction) = core
+                .frequency_fraction()
+                .filter(|_| self.config.cpu.frequency)
+            {
+                let fraction = self
+                    .animator
+                    .value(&format!("{} frequency", core.name), fraction);
+                set_frequency_source(ctx, core);
+                ctx.rectangle(x, y + CORE_CELL - 2., CORE_CELL * fraction, 2.);
+                ctx.fill()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A row per core of its usage over the heatmap's window, newest on the right, above the
+    /// gauge and the core grid if there is one.
+    fn draw_heatmap(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let cores = group_cores(
+            &self.sample.cores,
+            self.sampler.topology(),
+            self.config.cpu.group,
+        );
+        if cores.is_empty() {
+            return Ok(());
+        }
+        let mut bottom = gauge_center_y - GAUGE_RADIUS - 34.;
+        if self.is_visible(Panel::Cpu) && !self.config.cpu.layout.rings(cores.len()) {
+            let (_, rows) = grid_shape(cores.len(), 4.);
+            bottom -= rows as f64 * (CORE_CELL + CORE_GAP) + 6.;
         }
+        let row_height = (HEATMAP_HEIGHT / cores.len() as f64).min(6.);
+        let top = bottom - row_height * cores.len() as f64;
+        let left = gauge_center_x - HEATMAP_LENGTH / 2.;
+        let window = self.config.heatmap.window;
+
+        ctx.save()?;
+        ctx.rectangle(left, top, HEATMAP_LENGTH, bottom - top);
+        ctx.clip();
+        for (row, core) in cores.iter().enumerate() {
+            let Some(history) = self.core_history.get(&core.name) else {
+                continue;
+            };
+            let step = HEATMAP_LENGTH / history.points(window) as f64;
+            let scroll = self.scroll_offset(history, window);
+            let y = top + row as f64 * row_height;
+            for (i, bucket) in history.window(window).enumerate() {
+                let (red, green, blue, alpha) =
+                    self.config.heatmap.colormap.color(bucket.avg / 100.);
+                ctx.set_source_rgba(red, green, blue, alpha);
+                // Cells overlap a little so antialiasing doesn't leave seams between them.
+                let x = left + HEATMAP_LENGTH - (i as f64 + 1. - scroll) * step;
+                ctx.rectangle(x, y, step + 0.5, row_height);
+                ctx.fill()?;
+            }
+        }
+        ctx.restore()?;
+
+        ctx.set_source_rgba(1., 1., 1., 0.2);
+        ctx.set_line_width(1.);
+        ctx.rectangle(
+            left - 0.5,
+            top - 0.5,
+            HEATMAP_LENGTH + 1.,
+            bottom - top + 1.,
+        );
+        ctx.stroke()?;
+        Ok(())
+    }
+
+    /// The 1, 5 and 15-minute load averages centered above `y`, colored once they exceed the
+    /// number of CPUs, and optionally the recent 1-minute load above them. Says "n/a" where the
+    /// system doesn't report a load average.
+    fn draw_load(&mut self, x: f64, y: f64, ctx: &cairo::Context) -> Result<()> {
+        let text = match self.sample.load {
+            Some(load) => {
+                let percent = load.percent_of(self.sample.cores.len());
+                let level = self.config.thresholds.load.level(percent.one);
+                let load = if self.config.load.normalize {
+                    let per_core = |percent: f64| percent / 100.;
+                    (
+                        per_core(percent.one),
+                        per_core(percent.five),
+                        per_core(percent.fifteen),
+                    )
+                } else {
+                    (load.one, load.five, load.fifteen)
+                };
+                if level == Level::Normal {
+                    ctx.set_source_rgba(1., 1., 1., 0.6);
+                } else {
+                    set_level_source(
+                        ctx,
+                        &mut self.animator,
+                        self.config.thresholds.pulse,
+                        level,
+                        PINK,
+                    );
+                }
+                level.label(&format!("{:.2} {:.2} {:.2}", load.0, load.1, load.2))
+            }
+            None => {
+                ctx.set_source_rgba(1., 1., 1., 0.6);
+                "n/a".to_string()
+            }
+        };
+        self.text_centered_at(&text, x, y, 10., ctx)?;
+
+        if self.config.load.graph {
+            ctx.set_source_rgba(1., 1., 1., 0.6);
+            sparkline(
+                &self.load,
+                self.config.load.window,
+                x - SPARKLINE_LENGTH / 2.,
+                y - 14.,
+                ctx,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Fills the pill at `y` with how full the disk mounted at `mount_point` is, and returns the
+    /// label for it. The pill stays empty and the label says "n/a" if nothing is mounted there or
+    /// its size can't be read.
+    fn fill_partition(
+        &mut self,
+        mount_point: &str,
+        key: &str,
+        y: f64,
+        color: (f64, f64, f64),
+        ctx: &cairo::Context,
+    ) -> Result<String> {
+        let (gauge_center_x, _) = self.gauge_center();
+        let Some(used) = self
+            .sample
+            .disk(mount_point)
+            .and_then(|disk| disk.space.frac())
+        else {
+            return Ok(format!("n/a {mount_point}"));
+        };
+        let used = self.animator.value(key, used);
+        let level = self.config.thresholds.disk.level(used * 100.);
+
+        set_level_source(
+            ctx,
+            &mut self.animator,
+            self.config.thresholds.pulse,
+            level,
+            color,
+        );
+        ctx.move_to(gauge_center_x + GAUGE_RADIUS + PILL_MARGIN, y);
+        ctx.rel_line_to(PILL_LENGTH * used, 0.);
+        ctx.stroke()?;
+        Ok(level.label(&format!("{:.1}% {mount_point}", used * 100.)))
+    }
+
+    /// Root and boot partition pills to the right of the gauge, and the I/O graph past them.
+    fn draw_disk(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let disk_units = self.config.units.format(self.config.units.disk);
 
         ctx.set_source_rgba(1., 1., 1., 0.6);
         ctx.set_line_width(1.);
         self.pill(
-            gauge_center_x + gauge_radius + PILL_MARGIN,
+            gauge_center_x + GAUGE_RADIUS + PILL_MARGIN,
             gauge_center_y - 2.,
             PILL_LENGTH,
             6.,
             ctx,
         )?;
         self.pill(
-            gauge_center_x + gauge_radius + PILL_MARGIN,
+            gauge_center_x + GAUGE_RADIUS + PILL_MARGIN,
             gauge_center_y + 10.,
             PILL_LENGTH,
             6.,
             ctx,
         )?;
 
-        let root_partition = self
-            .disks
-            .iter()
-            .find(|disk| disk.mount_point() == Path::new("/"))
-            .expect("must have root partition");
-        let root_partition_used = disk_used_frac(root_partition);
-
         ctx.set_line_cap(cairo::LineCap::Round);
-        ctx.set_source_rgb(94. / 255., 1., 108. / 255.);
-        ctx.move_to(
-            gauge_center_x + gauge_radius + PILL_MARGIN,
-            gauge_center_y + 1.,
-        );
-        ctx.rel_line_to(PILL_LENGTH * root_partition_used, 0.);
-        ctx.stroke()?;
-
-        let boot_partition = self
-            .disks
-            .iter()
-            .find(|disk| disk.mount_point() == Path::new("/boot/efi/"))
-            .expect("must have boot partition");
-        let boot_partition_used = disk_used_frac(boot_partition);
-
-        ctx.set_source_rgb(212. / 255., 79. / 255., 126. / 255.);
-        ctx.move_to(
-            gauge_center_x + gauge_radius + PILL_MARGIN,
-            gauge_center_y + 13.,
-        );
-        ctx.rel_line_to(PILL_LENGTH * boot_partition_used, 0.);
-        ctx.stroke()?;
+        let root_label = self.fill_partition("/", "root", gauge_center_y + 1., GREEN, ctx)?;
+        let boot_label =
+            self.fill_partition("/boot/efi", "boot", gauge_center_y + 13., PINK, ctx)?;
 
-        let rect_origin_x = gauge_center_x + gauge_radius + PILL_LENGTH + PILL_MARGIN * 2.;
+        let rect_origin_x = gauge_center_x + GAUGE_RADIUS + PILL_LENGTH + PILL_MARGIN * 2.;
         let rect_origin_y = gauge_center_y - 7.;
         let rect_size_x = 15.;
         let rect_size_y = self.height as f64 - rect_origin_y;
@@ -457,27 +1055,19 @@ impl App {
 
         ctx.set_font_size(10.);
         ctx.move_to(text_x, rect_origin_y + 10.);
-        ctx.show_text(&format!(
-            "{:.1}% {}",
-            root_partition_used * 100.,
-            root_partition.mount_point().display()
-        ))?;
+        ctx.show_text(&root_label)?;
         ctx.move_to(text_x, rect_origin_y + 22.);
-        ctx.show_text(&format!(
-            "{:.1}% {}",
-            boot_partition_used * 100.,
-            boot_partition.mount_point().display()
-        ))?;
+        ctx.show_text(&boot_label)?;
 
         ctx.move_to(text_x + 100., rect_origin_y + 10.);
         ctx.show_text(&format!(
             "  {}",
-            format_bytes(*self.read_bytes_points.front().unwrap())
+            disk_units.rate_or_na(self.sample.disk_read)
         ))?;
         ctx.move_to(text_x + 100., rect_origin_y + 22.);
         ctx.show_text(&format!(
             "  {}",
-            format_bytes(*self.written_bytes_points.front().unwrap())
+            disk_units.rate_or_na(self.sample.disk_write)
         ))?;
 
         let rect_origin_x = text_x + 150.;
@@ -498,63 +1088,195 @@ impl App {
         ctx.rel_line_to(0., rect_size_y);
         ctx.stroke()?;
 
-        ctx.set_source_rgb(212. / 255., 79. / 255., 126. / 255.);
-        self.graph_byte_values(
-            &self.read_bytes_points,
-            rect_origin_x + rect_size_x + 3.,
-            self.height as f64,
+        let disk = &self.config.disk;
+        let maxima = self.disk_axis.maxima(
+            disk.scale,
+            disk.max,
+            graph_data_maxima(disk, &self.read_rate, &self.write_rate),
+        );
+        self.draw_graph(
+            [&self.read_rate, &self.write_rate],
+            disk,
+            maxima,
+            disk_units,
+            (rect_origin_x + rect_size_x + 3., self.height as f64),
             ctx,
         )?;
 
-        ctx.set_source_rgb(94. / 255., 1., 108. / 255.);
-        self.graph_byte_values(
-            &self.written_bytes_points,
-            rect_origin_x + rect_size_x + 3.,
-            self.height as f64,
-            ctx,
-        )?;
+        Ok(())
+    }
+
+    /// Selected temperatures and fans above the disk pills, each with a graph of its recent
+    /// readings. Nothing is drawn on machines without sensors.
+    fn draw_sensors(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let readings = self
+            .sample
+            .sensors
+            .iter()
+            .filter(|reading| self.config.sensors.selects(&reading.label))
+            .take(SENSOR_ROWS)
+            .collect_vec();
+
+        let text_x = gauge_center_x + GAUGE_RADIUS + PILL_MARGIN;
+        let bottom_y = gauge_center_y - 20.;
+        // Machines without hwmon, like most virtual machines, say so instead of showing nothing.
+        if readings.is_empty() {
+            ctx.set_source_rgba(1., 1., 1., 0.3);
+            ctx.set_font_size(10.);
+            ctx.move_to(text_x, bottom_y);
+            ctx.show_text("no sensors")?;
+            return Ok(());
+        }
+        let top_y = bottom_y - (readings.len() - 1) as f64 * SENSOR_ROW_HEIGHT;
+        ctx.set_source_rgba(1., 1., 1., 0.6);
+        ctx.set_font_size(32.);
+        ctx.move_to(text_x, top_y - 14.);
+        ctx.show_text("\u{f2c9} ")?;
+
+        ctx.set_font_size(10.);
+        for (i, reading) in readings.iter().enumerate() {
+            let y = top_y + i as f64 * SENSOR_ROW_HEIGHT;
+            let level = reading.level(&self.config.thresholds.temperature);
+            let (value, color) = match reading.kind {
+                SensorKind::Temperature => (format!("{:.0}°C", reading.value), PINK),
+                SensorKind::Fan => (format!("{:.0} RPM", reading.value), GREEN),
+            };
+            let label = if reading.label.chars().count() > 20 {
+                format!("{}…", reading.label.chars().take(19).collect::<String>())
+            } else {
+                reading.label.clone()
+            };
+
+            ctx.set_source_rgba(1., 1., 1., 0.6);
+            ctx.move_to(text_x, y);
+            ctx.show_text(&level.label(&label))?;
+            ctx.move_to(text_x + 110., y);
+            ctx.show_text(&value)?;
+
+            if let Some(history) = self.sensor_history.get(&reading.label) {
+                set_level_source(
+                    ctx,
+                    &mut self.animator,
+                    self.config.thresholds.pulse,
+                    level,
+                    color,
+                );
+                sparkline(
+                    history,
+                    self.config.sensors.window,
+                    text_x + PILL_LENGTH - SPARKLINE_LENGTH,
+                    y,
+                    ctx,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap and memory pills to the left of the gauge.
+    fn draw_memory(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
 
         ctx.set_source_rgba(1., 1., 1., 0.6);
         ctx.set_line_width(1.);
         self.pill(
-            gauge_center_x - gauge_radius - PILL_MARGIN - PILL_LENGTH,
+            gauge_center_x - GAUGE_RADIUS - PILL_MARGIN - PILL_LENGTH,
             gauge_center_y - 2.,
             PILL_LENGTH,
             6.,
             ctx,
         )?;
         self.pill(
-            gauge_center_x - gauge_radius - PILL_MARGIN - PILL_LENGTH,
+            gauge_center_x - GAUGE_RADIUS - PILL_MARGIN - PILL_LENGTH,
             gauge_center_y + 10.,
             PILL_LENGTH,
             6.,
             ctx,
         )?;
 
-        let frac_swap_used = self.system.used_swap() as f64 / self.system.total_swap() as f64;
         ctx.set_line_cap(cairo::LineCap::Round);
-        ctx.set_source_rgb(94. / 255., 1., 108. / 255.);
-        ctx.move_to(
-            gauge_center_x - gauge_radius - PILL_MARGIN,
-            gauge_center_y + 1.,
-        );
-        ctx.rel_line_to(-PILL_LENGTH * frac_swap_used, 0.);
-        ctx.stroke()?;
+        // Machines without swap get an empty pill rather than a NaN-long one.
+        let swap_text = match self
+            .sample
+            .swap
+            .zip(self.sample.swap.and_then(|swap| swap.frac()))
+        {
+            Some((swap, frac_swap_used)) => {
+                let frac_swap_used = self.animator.value("swap", frac_swap_used);
+                let swap_level = self.config.thresholds.swap.level(frac_swap_used * 100.);
+                set_level_source(
+                    ctx,
+                    &mut self.animator,
+                    self.config.thresholds.pulse,
+                    swap_level,
+                    GREEN,
+                );
+                ctx.move_to(
+                    gauge_center_x - GAUGE_RADIUS - PILL_MARGIN,
+                    gauge_center_y + 1.,
+                );
+                ctx.rel_line_to(-PILL_LENGTH * frac_swap_used, 0.);
+                ctx.stroke()?;
+                // Swap in zram or zswap is cheaper than swap on disk, so it's set apart from the
+                // start of the bar.
+                if let Some(breakdown) = self
+                    .sample
+                    .swap_breakdown
+                    .filter(|_| swap_level == Level::Normal)
+                {
+                    let frac_in_ram = self
+                        .animator
+                        .value("swap in ram", breakdown.in_ram() as f64 / swap.total as f64);
+                    ctx.set_source_rgb(PALE_GREEN.0, PALE_GREEN.1, PALE_GREEN.2);
+                    ctx.move_to(
+                        gauge_center_x - GAUGE_RADIUS - PILL_MARGIN,
+                        gauge_center_y + 1.,
+                    );
+                    ctx.rel_line_to(-PILL_LENGTH * frac_in_ram, 0.);
+                    ctx.stroke()?;
+                }
+                swap_level.label(&format!("SWAP {:5.1}%", 100. * frac_swap_used))
+            }
+            None => "SWAP   none".to_owned(),
+        };
 
-        let frac_mem_used = self.system.used_memory() as f64 / self.system.total_memory() as f64;
-        ctx.set_source_rgb(212. / 255., 79. / 255., 126. / 255.);
-        ctx.move_to(
-            gauge_center_x - gauge_radius - PILL_MARGIN,
-            gauge_center_y + 13.,
-        );
-        ctx.rel_line_to(-PILL_LENGTH * frac_mem_used, 0.);
-        ctx.stroke()?;
+        let memory_text = match self.sample.memory.and_then(|memory| memory.frac()) {
+            Some(frac_mem_used) => {
+                let frac_mem_used = self.animator.value("memory", frac_mem_used);
+                let memory_level = self.config.thresholds.memory.level(frac_mem_used * 100.);
+                set_level_source(
+                    ctx,
+                    &mut self.animator,
+                    self.config.thresholds.pulse,
+                    memory_level,
+                    PINK,
+                );
+                match self
+                    .sample
+                    .memory_breakdown
+                    .filter(|memory| memory_level == Level::Normal && memory.total > 0)
+                {
+                    Some(memory) => self.draw_memory_breakdown(&memory, ctx)?,
+                    None => {
+                        ctx.move_to(
+                            gauge_center_x - GAUGE_RADIUS - PILL_MARGIN,
+                            gauge_center_y + 13.,
+                        );
+                        ctx.rel_line_to(-PILL_LENGTH * frac_mem_used, 0.);
+                        ctx.stroke()?;
+                    }
+                }
+                memory_level.label(&format!("MEM  {:5.1}%", 100. * frac_mem_used))
+            }
+            None => "MEM     n/a".to_owned(),
+        };
 
         let rect_size_x = 15.;
         let rect_origin_y = gauge_center_y - 7.;
         let rect_size_y = self.height as f64 - rect_origin_y;
         let rect_origin_x =
-            gauge_center_x - gauge_radius - PILL_LENGTH - PILL_MARGIN * 2. - rect_size_x;
+            gauge_center_x - GAUGE_RADIUS - PILL_LENGTH - PILL_MARGIN * 2. - rect_size_x;
         ctx.set_source_rgba(1., 1., 1., 0.6);
         ctx.move_to(rect_origin_x + rect_size_x + 2., rect_origin_y);
         ctx.rel_line_to(0., rect_size_y);
@@ -583,24 +1305,211 @@ impl App {
         ctx.show_text(text)?;
 
         ctx.set_font_size(10.);
-        let text = format!(
-            "SWAP {:5.1}%",
-            100. * self.system.used_swap() as f64 / self.system.total_swap() as f64
-        );
         ctx.move_to(
-            text_x - ctx.text_extents(&text)?.width(),
+            text_x - ctx.text_extents(&swap_text)?.width(),
             rect_origin_y + 10.,
         );
-        ctx.show_text(&text)?;
-        let text = format!(
-            "MEM  {:5.1}%",
-            100. * self.system.used_memory() as f64 / self.system.total_memory() as f64
-        );
+        ctx.show_text(&swap_text)?;
         ctx.move_to(
-            text_x - ctx.text_extents(&text)?.width(),
+            text_x - ctx.text_extents(&memory_text)?.width(),
             rect_origin_y + 22.,
         );
+        ctx.show_text(&memory_text)?;
+
+        Ok(())
+    }
+
+    /// Stacks the memory pill by what the memory is used for, starting from the gauge, and
+    /// lists the amounts in the same colors above the pills.
+    fn draw_memory_breakdown(
+        &mut self,
+        memory: &MemoryBreakdown,
+        ctx: &cairo::Context,
+    ) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let mut x = gauge_center_x - GAUGE_RADIUS - PILL_MARGIN;
+        for ((segment, bytes), (red, green, blue)) in MemoryBreakdown::SEGMENTS
+            .into_iter()
+            .zip(memory.segments())
+            .zip(MEMORY_COLORS)
+        {
+            let frac = self.animator.value(
+                &format!("memory {segment}"),
+                bytes as f64 / memory.total as f64,
+            );
+            ctx.set_source_rgb(red, green, blue);
+            ctx.move_to(x, gauge_center_y + 13.);
+            ctx.rel_line_to(-PILL_LENGTH * frac, 0.);
+            ctx.stroke()?;
+            x -= PILL_LENGTH * frac;
+        }
+
+        // Most machines don't reserve any huge pages.
+        let units = self.config.units.format(DataUnit::Bytes);
+        let pieces = MemoryBreakdown::SEGMENTS
+            .into_iter()
+            .zip(memory.segments())
+            .zip(MEMORY_COLORS)
+            .filter(|&((segment, bytes), _)| segment != "huge" || bytes > 0)
+            .map(|((_, bytes), (red, green, blue))| {
+                (
+                    format!("{} ", units.amount(bytes as f64)),
+                    (red, green, blue, 1.),
+                )
+            })
+            .chain([(
+                format!("avail {}", units.amount(memory.available as f64)),
+                (1., 1., 1., 0.6),
+            )])
+            .collect_vec();
+        ctx.set_font_size(8.);
+        let mut width = 0.;
+        for (text, _) in &pieces {
+            width += ctx.text_extents(text)?.x_advance();
+        }
+        ctx.move_to(
+            gauge_center_x - GAUGE_RADIUS - PILL_MARGIN - (PILL_LENGTH + width) / 2.,
+            gauge_center_y - 6.,
+        );
+        for (text, (red, green, blue, alpha)) in pieces {
+            ctx.set_source_rgba(red, green, blue, alpha);
+            ctx.show_text(&text)?;
+        }
+        Ok(())
+    }
+
+    /// Battery pill above the memory pills, with the charge, power draw and time to empty or
+    /// full. Nothing is drawn on machines without a battery.
+    fn draw_battery(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let Some(battery) = &self.sample.battery else {
+            return Ok(());
+        };
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let pill_right_x = gauge_center_x - GAUGE_RADIUS - PILL_MARGIN;
+        let pill_y = gauge_center_y - 22.;
+
+        ctx.set_source_rgba(1., 1., 1., 0.6);
+        ctx.set_line_width(1.);
+        self.pill(pill_right_x - PILL_LENGTH, pill_y, PILL_LENGTH, 6., ctx)?;
+
+        let charge = self.animator.value("battery", battery.charge / 100.);
+        let level = battery.level();
+        let color = if battery.state == ChargeState::Charging {
+            GREEN
+        } else {
+            PINK
+        };
+        ctx.set_line_cap(cairo::LineCap::Round);
+        set_level_source(
+            ctx,
+            &mut self.animator,
+            self.config.thresholds.pulse,
+            level,
+            color,
+        );
+        ctx.move_to(pill_right_x, pill_y + 3.);
+        ctx.rel_line_to(-PILL_LENGTH * charge, 0.);
+        ctx.stroke()?;
+
+        let mut text = format!("\u{f0079} {:.0}% {}", charge * 100., battery.state.label());
+        if let Some(power) = self.battery_power.latest() {
+            text += &format!("  {power:.1}W");
+        }
+        let remaining = self
+            .battery_power
+            .window_avg(self.config.battery.window)
+            .and_then(|power| battery.time_remaining(power));
+        if let Some(remaining) = remaining {
+            let minutes = remaining.as_secs() / 60;
+            let until = match battery.state {
+                ChargeState::Charging => "full",
+                _ => "empty",
+            };
+            text += &format!("  {}h{:02}m to {until}", minutes / 60, minutes % 60);
+        }
+        let text = level.label(&text);
+        ctx.set_source_rgba(1., 1., 1., 0.6);
+        ctx.set_font_size(10.);
+        ctx.move_to(pill_right_x - ctx.text_extents(&text)?.width(), pill_y - 6.);
         ctx.show_text(&text)?;
+        Ok(())
+    }
+
+    /// The busiest processes by CPU and by memory above the network graph, and by disk I/O
+    /// above the disk graph.
+    fn draw_processes(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let bottom_y = gauge_center_y - 45.;
+        let memory_total = self.sample.memory.map(|memory| memory.total);
+        let disk_units = self.config.units.format(self.config.units.disk);
+
+        let cpu_rows = self
+            .sample
+            .top_cpu
+            .iter()
+            .map(|process| (process, format!("{:5.1}%", process.cpu)))
+            .collect_vec();
+        let memory_rows = self
+            .sample
+            .top_memory
+            .iter()
+            .map(|process| {
+                let percent = memory_total
+                    .and_then(|total| Usage::new(process.memory, total))
+                    .and_then(|memory| memory.percent());
+                (
+                    process,
+                    percent.map_or("   n/a".to_owned(), |percent| format!("{percent:5.1}%")),
+                )
+            })
+            .collect_vec();
+        // Lines up with the left edge of the network graph.
+        let network_graph_x =
+            gauge_center_x - GAUGE_RADIUS - PILL_LENGTH - GRAPH_LENGTH - PILL_MARGIN * 2. - 178.;
+        process_list(
+            &[
+                ("\u{f4bc} TOP CPU", &cpu_rows[..]),
+                ("\u{efc5} TOP MEM", &memory_rows[..]),
+            ],
+            network_graph_x,
+            bottom_y,
+            GRAPH_LENGTH,
+            ctx,
+        )?;
+
+        let io_rows = self
+            .sample
+            .top_io
+            .iter()
+            .map(|process| {
+                let text = format!(
+                    "\u{f139} {}  \u{f13a} {}",
+                    disk_units.rate(process.read_rate),
+                    disk_units.rate(process.write_rate)
+                );
+                (process, text)
+            })
+            .collect_vec();
+        // Lines up with the left edge of the disk graph, and is wider to fit both rates.
+        let disk_graph_x = gauge_center_x + GAUGE_RADIUS + PILL_LENGTH + PILL_MARGIN * 2. + 178.;
+        process_list(
+            &[("\u{f02ca} TOP I/O", &io_rows[..])],
+            disk_graph_x,
+            bottom_y,
+            GRAPH_LENGTH + 80.,
+            ctx,
+        )
+    }
+
+    /// Network rates and graph, left of the memory panel.
+    fn draw_network(&mut self, ctx: &cairo::Context) -> Result<()> {
+        let (gauge_center_x, gauge_center_y) = self.gauge_center();
+        let network_units = self.config.units.format(self.config.units.network);
+        let rect_size_x = 15.;
+        let rect_origin_y = gauge_center_y - 7.;
+        let rect_size_y = self.height as f64 - rect_origin_y;
+        let text_x =
+            gauge_center_x - GAUGE_RADIUS - PILL_LENGTH - PILL_MARGIN * 2. - rect_size_x + 5.;
 
         ctx.set_font_size(32.);
         ctx.move_to(text_x - 155., rect_origin_y - 12.);
@@ -610,12 +1519,12 @@ impl App {
         ctx.move_to(text_x - 155., rect_origin_y + 10.);
         ctx.show_text(&format!(
             "  {}",
-            format_bytes(*self.uploaded_bytes_points.front().unwrap())
+            network_units.rate_or_na(self.sample.upload)
         ))?;
         ctx.move_to(text_x - 155., rect_origin_y + 22.);
         ctx.show_text(&format!(
             "  {}",
-            format_bytes(*self.downloaded_bytes_points.front().unwrap())
+            network_units.rate_or_na(self.sample.download)
         ))?;
 
         let rect_origin_x = text_x - 150. - rect_size_x;
@@ -636,19 +1545,18 @@ impl App {
         ctx.rel_line_to(0., rect_size_y);
         ctx.stroke()?;
 
-        ctx.set_source_rgb(212. / 255., 79. / 255., 126. / 255.);
-        self.graph_byte_values(
-            &self.downloaded_bytes_points,
-            rect_origin_x - GRAPH_LENGTH - 3.,
-            self.height as f64,
-            ctx,
-        )?;
-
-        ctx.set_source_rgb(94. / 255., 1., 108. / 255.);
-        self.graph_byte_values(
-            &self.uploaded_bytes_points,
-            rect_origin_x - GRAPH_LENGTH - 3.,
-            self.height as f64,
+        let network = &self.config.network;
+        let maxima = self.network_axis.maxima(
+            network.scale,
+            network.max,
+            graph_data_maxima(network, &self.download_rate, &self.upload_rate),
+        );
+        self.draw_graph(
+            [&self.download_rate, &self.upload_rate],
+            network,
+            maxima,
+            network_units,
+            (rect_origin_x - GRAPH_LENGTH - 3., self.height as f64),
             ctx,
         )?;
 
@@ -691,23 +1599,174 @@ impl App {
         Ok(())
     }
 
+    /// Draws a disk or network graph of two series, the first in pink and the second in green,
+    /// with its bottom left corner at `origin`.
+    fn draw_graph(
+        &self,
+        series: [&History; 2],
+        graph: &GraphConfig,
+        maxima: [f64; 2],
+        units: UnitFormat,
+        (origin_x, origin_y): (f64, f64),
+        ctx: &cairo::Context,
+    ) -> Result<()> {
+        self.graph_axis(
+            graph,
+            maxima[0].max(maxima[1]),
+            units,
+            origin_x,
+            origin_y,
+            ctx,
+        )?;
+
+        for (i, (values, max, color)) in itertools::izip!(series, maxima, [PINK, GREEN]).enumerate()
+        {
+            let (axis_y, full_height, direction) = match graph.style {
+                Style::Mirrored if i == 0 => (origin_y - GRAPH_HEIGHT / 2., GRAPH_HEIGHT / 2., -1.),
+                Style::Mirrored => (origin_y - GRAPH_HEIGHT / 2., GRAPH_HEIGHT / 2., 1.),
+                _ => (origin_y, GRAPH_HEIGHT, 1.),
+            };
+            let area = SeriesArea {
+                origin_x,
+                axis_y,
+                full_height,
+                direction,
+                max,
+            };
+            let stacked_on = (graph.style == Style::Stacked && i > 0).then(|| series[i - 1]);
+            self.graph_byte_values(values, stacked_on, graph, color, area, ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Draws one series of a graph in `area`. A stacked series starts at the top of the one it
+    /// sits on.
     fn graph_byte_values(
         &self,
-        values: &VecDeque<u64>,
+        values: &History,
+        stacked_on: Option<&History>,
+        graph: &GraphConfig,
+        (red, green, blue): (f64, f64, f64),
+        area: SeriesArea,
+        ctx: &cairo::Context,
+    ) -> Result<()> {
+        let SeriesArea {
+            origin_x,
+            axis_y,
+            full_height,
+            direction,
+            max,
+        } = area;
+        let scale = graph.scale;
+        let bar_width = GRAPH_LENGTH / values.points(graph.window) as f64;
+        let scroll = self.scroll_offset(values, graph.window);
+        let y_at = |value| axis_y - direction * scale.height(value, max, full_height);
+        let bases = stacked_on
+            .map(|below| {
+                below
+                    .window(graph.window)
+                    .map(|bucket| bucket.avg)
+                    .collect_vec()
+            })
+            .unwrap_or_default();
+
+        if graph.gradient && graph.style != Style::Line && graph.style != Style::Smooth {
+            let pattern = LinearGradient::new(0., axis_y - direction * full_height, 0., axis_y);
+            pattern.add_color_stop_rgba(0., red, green, blue, 0.9);
+            pattern.add_color_stop_rgba(1., red, green, blue, 0.15);
+            ctx.set_source(pattern)?;
+        } else {
+            ctx.set_source_rgb(red, green, blue);
+        }
+
+        if graph.style == Style::Bars {
+            for (i, bucket) in values.window(graph.window).enumerate() {
+                let bar_x = origin_x + GRAPH_LENGTH - (i as f64 - scroll) * bar_width;
+                let top = y_at(bucket.avg);
+                ctx.rectangle(bar_x, top.min(axis_y), bar_width, (top - axis_y).abs());
+
+                // Rolled-up buckets also get a thin whisker spanning their min and max.
+                let (min_y, max_y) = (y_at(bucket.min), y_at(bucket.max));
+                ctx.rectangle(
+                    bar_x + bar_width / 2. - 0.5,
+                    min_y.min(max_y),
+                    1.,
+                    (max_y - min_y).abs(),
+                );
+                ctx.fill()?;
+            }
+            return Ok(());
+        }
+
+        // Everything else is a path through the middle of where each bar would be.
+        let point_x =
+            |i: usize| origin_x + GRAPH_LENGTH - (i as f64 - scroll) * bar_width + bar_width / 2.;
+        let tops = values
+            .window(graph.window)
+            .enumerate()
+            .map(|(i, bucket)| {
+                let base = bases.get(i).copied().unwrap_or_default();
+                (point_x(i), y_at(base + bucket.avg))
+            })
+            .collect_vec();
+        let (min_y, max_y) = (axis_y - full_height, axis_y + full_height);
+        let smooth = graph.style == Style::Smooth;
+
+        ctx.new_path();
+        graph::trace(ctx, &tops, smooth, min_y, max_y);
+        if graph.style.is_filled() {
+            let bottoms = (0..tops.len())
+                .rev()
+                .map(|i| (point_x(i), y_at(bases.get(i).copied().unwrap_or_default())))
+                .collect_vec();
+            graph::trace(ctx, &bottoms, false, min_y, max_y);
+            ctx.close_path();
+            ctx.fill()?;
+
+            // Outline the top edge so thin areas are still visible.
+            ctx.set_source_rgb(red, green, blue);
+            graph::trace(ctx, &tops, false, min_y, max_y);
+        }
+        ctx.set_line_width(1.5);
+        ctx.set_line_join(cairo::LineJoin::Round);
+        ctx.stroke()?;
+        Ok(())
+    }
+
+    /// How far a series' points should be shifted back toward where they were before the last
+    /// sample, so graphs with a point per sample scroll smoothly instead of jumping.
+    fn scroll_offset(&self, values: &History, window: TimeWindow) -> f64 {
+        if values.resolution(window) > self.interval {
+            return 0.;
+        }
+        1. - self.animator.scroll_progress()
+    }
+
+    /// Draws the gridlines and max-value label behind a graph, as configured.
+    fn graph_axis(
+        &self,
+        graph: &GraphConfig,
+        max: f64,
+        units: UnitFormat,
         origin_x: f64,
         origin_y: f64,
         ctx: &cairo::Context,
     ) -> Result<()> {
-        let read_bytes_max_val = 1.0f64.max(*values.iter().max().unwrap() as f64);
-        for (i, read_bytes_point) in values.iter().enumerate() {
-            let rect_height = *read_bytes_point as f64 / read_bytes_max_val * GRAPH_HEIGHT;
-            ctx.rectangle(
-                origin_x + GRAPH_LENGTH - i as f64 * GRAPH_BAR_WIDTH,
-                origin_y - rect_height,
-                GRAPH_BAR_WIDTH,
-                rect_height,
-            );
-            ctx.fill()?;
+        ctx.set_source_rgba(1., 1., 1., 0.15);
+        ctx.set_line_width(1.);
+        for i in 1..=graph.gridlines {
+            let y = origin_y - GRAPH_HEIGHT * i as f64 / graph.gridlines as f64;
+            ctx.move_to(origin_x, y.round() + 0.5);
+            ctx.rel_line_to(GRAPH_LENGTH, 0.);
+        }
+        ctx.stroke()?;
+
+        // Independently scaled series have no single value to put on the axis.
+        if graph.max_label && graph.scale.is_shared() {
+            ctx.set_source_rgba(1., 1., 1., 0.6);
+            ctx.set_font_size(8.);
+            ctx.move_to(origin_x + 2., origin_y - GRAPH_HEIGHT - 2.);
+            ctx.show_text(&units.rate(max))?;
         }
         Ok(())
     }
@@ -745,9 +1804,20 @@ impl Dispatch<wl_registry::WlRegistry, ()> for App {
                         state.shm = Some(shm);
                     }
                     "wl_output" => {
-                        let output =
-                            registry.bind::<wl_output::WlOutput, _, _>(name, version, qhandle, ());
-                        state.outputs.push(output);
+                        // Output names were added in version 4.
+                        let output = registry.bind::<wl_output::WlOutput, _, _>(
+                            name,
+                            version.min(4),
+                            qhandle,
+                            (),
+                        );
+                        state.outputs.push(Output {
+                            output,
+                            name: None,
+                            description: None,
+                            size: None,
+                            scale: 1,
+                        });
                     }
                     "zwlr_layer_shell_v1" => {
                         let layer_shell = registry
@@ -859,8 +1929,11 @@ impl Dispatch<wl_callback::WlCallback, ()> for App {
                 error!("Frame callback render error: {}", e);
             }
 
-            // Schedule next frame callback after a 1-second delay
-            thread::sleep(RENDER_INTERVAL);
+            // Keep drawing at the compositor's frame rate while something is moving, otherwise
+            // idle until the next sample is due.
+            if !state.animator.is_animating() {
+                state.idle();
+            }
             if let Some(surface) = &state.surface {
                 let _callback = surface.frame(qhandle, ());
             }
@@ -897,15 +1970,36 @@ impl Dispatch<xdg_wm_base::XdgWmBase, ()> for App {
 impl Dispatch<wl_output::WlOutput, ()> for App {
     fn event(
         state: &mut Self,
-        _proxy: &wl_output::WlOutput,
+        proxy: &wl_output::WlOutput,
         event: wl_output::Event,
         _data: &(),
         _conn: &Connection,
         _qhandle: &QueueHandle<Self>,
     ) {
-        if let wl_output::Event::Scale { factor } = event {
-            info!("Output scale factor: {}", factor);
-            state.scale_factor = factor;
+        let Some(output) = state.outputs.iter_mut().find(|o| &o.output == proxy) else {
+            return;
+        };
+        match event {
+            wl_output::Event::Scale { factor } => {
+                info!("Output scale factor: {}", factor);
+                output.scale = factor;
+                if state
+                    .selected_output
+                    .as_ref()
+                    .is_none_or(|selected| selected == proxy)
+                {
+                    state.scale_factor = factor;
+                }
+            }
+            wl_output::Event::Name { name } => output.name = Some(name),
+            wl_output::Event::Description { description } => output.description = Some(description),
+            wl_output::Event::Mode {
+                flags: wayland_client::WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => output.size = Some((width, height)),
+            _ => {}
         }
     }
 }
@@ -943,6 +2037,7 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for App {
             }
             zwlr_layer_surface_v1::Event::Closed => {
                 info!("Layer surface closed");
+                state.closed = true;
             }
             _ => {}
         }
@@ -952,18 +2047,89 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for App {
 fn main() -> Result<()> {
     env_logger::init();
 
+    let cli = Cli::parse();
+    match cli.command {
+        None => run(cli.config, RunArgs::default()),
+        Some(Command::Run(args)) => run(cli.config, args),
+        Some(Command::Snapshot(args)) => snapshot(cli.config, args),
+        Some(Command::Stream(args)) => stream::run(
+            &load_config(cli.config)?,
+            args.format,
+            args.interval.duration(),
+            args.count,
+        ),
+        Some(Command::CheckConfig) => check_config(cli.config),
+        Some(Command::ListOutputs) => list_outputs(),
+        Some(Command::ListDisks) => {
+            cli::list_disks(load_config(cli.config)?.units.format(DataUnit::Bytes));
+            Ok(())
+        }
+        Some(Command::ListInterfaces) => {
+            cli::list_interfaces(load_config(cli.config)?.units.format(DataUnit::Bytes));
+            Ok(())
+        }
+        Some(Command::Msg(request)) => ipc::run_client(request),
+    }
+}
+
+fn run(config_path: Option<PathBuf>, args: RunArgs) -> Result<()> {
     let connection = Connection::connect_to_env().context("Failed to connect to Wayland")?;
     let mut event_queue = connection.new_event_queue();
     let qhandle = event_queue.handle();
 
-    let mut app = App::new();
+    let mut app = App::new(config_path, args.interval.duration());
+    // Kept until the widget exits, which removes the socket file.
+    let _socket = match ipc::socket_path() {
+        Some(path) => match ipc::listen(&path) {
+            Ok((commands, socket)) => {
+                app.commands = Some(commands);
+                Some(socket)
+            }
+            Err(e) => {
+                error!("Control socket unavailable: {:#}", e);
+                None
+            }
+        },
+        None => {
+            error!("XDG_RUNTIME_DIR is not set, control socket unavailable");
+            None
+        }
+    };
+    let _metrics_socket = if let Some(addr) = &args.metrics {
+        let metrics = SharedMetrics::default();
+        let socket = metrics::serve(addr, metrics.clone())?;
+        app.metrics = Some(metrics);
+        socket
+    } else {
+        None
+    };
 
     let _registry = connection.display().get_registry(&qhandle, ());
 
-    // Initial roundtrip to get globals
+    // Initial roundtrip to get globals, and another for the outputs to describe themselves
     event_queue
         .roundtrip(&mut app)
         .context("Failed to sync with compositor")?;
+    event_queue
+        .roundtrip(&mut app)
+        .context("Failed to get outputs")?;
+
+    if let Some(name) = &args.output {
+        let output = app
+            .outputs
+            .iter()
+            .find(|output| output.name.as_ref() == Some(name))
+            .with_context(|| {
+                let names = app
+                    .outputs
+                    .iter()
+                    .filter_map(|o| o.name.as_deref())
+                    .join(", ");
+                format!("No output named {name:?}, the outputs are: {names}")
+            })?;
+        app.scale_factor = output.scale;
+        app.selected_output = Some(output.output.clone());
+    }
 
     // Create surface and layer surface if we have the required globals
     if let (Some(compositor), Some(layer_shell)) = (&app.compositor, &app.layer_shell) {
@@ -972,8 +2138,8 @@ fn main() -> Result<()> {
 
         let layer_surface = layer_shell.get_layer_surface(
             &surface,
-            None,
-            zwlr_layer_shell_v1::Layer::Background,
+            app.selected_output.as_ref(),
+            args.layer.into(),
             "widget".to_string(),
             &qhandle,
             (),
@@ -1001,66 +2167,321 @@ fn main() -> Result<()> {
         return Err(anyhow::anyhow!("Missing required Wayland globals"));
     }
 
-    loop {
+    while !app.closed {
         event_queue.blocking_dispatch(&mut app)?;
     }
+    Ok(())
+}
+
+/// Draws the widget once into a PNG, taking two samples so the rates have values.
+fn snapshot(config_path: Option<PathBuf>, args: SnapshotArgs) -> Result<()> {
+    let mut app = App::new(config_path, args.interval.duration());
+    thread::sleep(app.interval);
+    app.refresh_system();
+    app.width = args.width;
+    app.height = args.height;
+    // Draw everything where it settles rather than at the start of its transition or scroll.
+    app.animator
+        .begin_frame(Instant::now() + app.config.animation.duration().max(app.interval));
+
+    let surface = app.draw_frame()?;
+    let mut file = File::create(&args.path)
+        .with_context(|| format!("Failed to create {}", args.path.display()))?;
+    surface
+        .write_to_png(&mut file)
+        .with_context(|| format!("Failed to write {}", args.path.display()))?;
+    println!("Wrote {}", args.path.display());
+    Ok(())
+}
+
+/// The config for one-off commands, which don't watch it for changes.
+fn load_config(config_path: Option<PathBuf>) -> Result<Config> {
+    match config_path.or_else(Config::default_path) {
+        Some(path) => Config::load(&path),
+        None => Ok(Config::default()),
+    }
 }
 
-fn disk_used_frac(disk: &Disk) -> f64 {
-    1. - (disk.total_space() - disk.available_space()) as f64 / disk.total_space() as f64
+fn check_config(config_path: Option<PathBuf>) -> Result<()> {
+    let path = config_path
+        .or_else(Config::default_path)
+        .context("Neither XDG_CONFIG_HOME nor HOME is set")?;
+    if !path.exists() {
+        println!("{} does not exist, the defaults apply", path.display());
+        return Ok(());
+    }
+    Config::load(&path)?;
+    println!("{} is valid", path.display());
+    Ok(())
 }
 
-fn format_bytes(bytes: u64) -> String {
-    if bytes < 1000 {
-        return format!("{bytes}B");
+fn list_outputs() -> Result<()> {
+    let connection = Connection::connect_to_env().context("Failed to connect to Wayland")?;
+    let mut event_queue = connection.new_event_queue();
+    let qhandle = event_queue.handle();
+    let mut app = App::new(None, Duration::from_secs(1));
+    let _registry = connection.display().get_registry(&qhandle, ());
+    event_queue
+        .roundtrip(&mut app)
+        .context("Failed to sync with compositor")?;
+    event_queue
+        .roundtrip(&mut app)
+        .context("Failed to get outputs")?;
+
+    for output in &app.outputs {
+        let size = output
+            .size
+            .map_or("unknown size".to_owned(), |(w, h)| format!("{w}x{h}"));
+        println!(
+            "{}\t{size} scale {}\t{}",
+            output.name.as_deref().unwrap_or("(unnamed)"),
+            output.scale,
+            output.description.as_deref().unwrap_or_default(),
+        );
     }
-    const UNITS: [&str; 5] = ["kB", "MB", "GB", "TB", "PB"];
-    let mut val = bytes as f64;
-    for unit in UNITS {
-        val /= 1024.;
-        if val < 1000. {
-            return format!("{val:.1}{unit}");
+    Ok(())
+}
+
+/// Largest value in view for each series of a graph, for scaling its axis.
+fn graph_data_maxima(graph: &GraphConfig, first: &History, second: &History) -> [f64; 2] {
+    if graph.style == Style::Stacked {
+        [first.stacked_window_max(second, graph.window); 2]
+    } else {
+        [
+            first.window_max(graph.window),
+            second.window_max(graph.window),
+        ]
+    }
+}
+
+/// Sets the color for a metric at `level`, pulsing it while critical if `pulse` is set.
+fn set_level_source(
+    ctx: &cairo::Context,
+    animator: &mut Animator,
+    pulse: bool,
+    level: Level,
+    normal: (f64, f64, f64),
+) {
+    let (red, green, blue) = level.color(normal);
+    let alpha = if pulse && level == Level::Critical {
+        animator.pulse()
+    } else {
+        1.
+    };
+    ctx.set_source_rgba(red, green, blue, alpha);
+}
+
+/// Where one series of a graph is drawn: from `axis_y` up to `full_height` away from it, upward
+/// if `direction` is 1 or downward if it is -1, with `max` reaching all the way.
+#[derive(Clone, Copy)]
+struct SeriesArea {
+    origin_x: f64,
+    axis_y: f64,
+    full_height: f64,
+    direction: f64,
+    max: f64,
+}
+
+/// Where a core's arc goes in the gauge: a quarter circle from the top, clockwise if
+/// `direction` is 1 and counterclockwise if it is -1.
+#[derive(Clone, Copy)]
+struct CoreArc {
+    center: (f64, f64),
+    radius: f64,
+    direction: f64,
+}
+
+impl CoreArc {
+    /// The same arc `by` further out, or further in if negative.
+    fn offset(self, by: f64) -> Self {
+        CoreArc {
+            radius: self.radius + by,
+            ..self
+        }
+    }
+
+    /// Adds the part of the arc between `from` and `to` to the path, as fractions of the
+    /// quarter circle.
+    fn add(&self, ctx: &cairo::Context, from: f64, to: f64) {
+        let top = 3. * PI / 2.;
+        let (center_x, center_y) = self.center;
+        let (start, end) = (
+            top + self.direction * from * PI / 2.,
+            top + self.direction * to * PI / 2.,
+        );
+        if self.direction > 0. {
+            ctx.arc(center_x, center_y, self.radius, start, end);
+        } else {
+            ctx.arc_negative(center_x, center_y, self.radius, start, end);
         }
     }
-    format!("{val:.1}PB")
 }
 
-fn push_within_limit<T>(values: &mut VecDeque<T>, new_value: T, limit: usize) {
-    values.push_front(new_value);
+/// What the core arcs are drawn with.
+struct RingStyle<'a> {
+    animator: &'a mut Animator,
+    thresholds: &'a ThresholdsConfig,
+    /// Whether to split the arcs by CPU mode.
+    breakdown: bool,
+}
 
-    while values.len() > limit {
-        values.pop_back();
+/// Draws a core's usage along `arc`. With `breakdown`, the arc is split into a segment per CPU
+/// mode unless the usage is past a threshold, which takes over the whole arc's color.
+fn core_arc(
+    ctx: &cairo::Context,
+    style: &mut RingStyle,
+    core: &CoreSample,
+    arc: CoreArc,
+) -> Result<()> {
+    let usage = style.animator.value(&core.name, core.usage);
+    let level = style.thresholds.cpu.level(usage);
+
+    match core
+        .breakdown
+        .filter(|_| style.breakdown && level == Level::Normal)
+    {
+        Some(modes) => {
+            let mut start = 0.;
+            for ((mode, percent), (red, green, blue)) in CpuBreakdown::MODES
+                .into_iter()
+                .zip(modes.values())
+                .zip(MODE_COLORS)
+            {
+                let percent = style
+                    .animator
+                    .value(&format!("{} {mode}", core.name), percent);
+                let end = start + percent / 100.;
+                ctx.set_source_rgb(red, green, blue);
+                arc.add(ctx, start, end);
+                ctx.stroke()?;
+                start = end;
+            }
+        }
+        None => {
+            set_level_source(
+                ctx,
+                style.animator,
+                style.thresholds.pulse,
+                level,
+                core_color(core),
+            );
+            arc.add(ctx, 0., usage / 100.);
+            ctx.stroke()?;
+        }
     }
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_format_bytes() {
-        assert_eq!(format_bytes(0), "0B");
-        assert_eq!(format_bytes(43), "43B");
-        assert_eq!(format_bytes(999), "999B");
-        assert_eq!(format_bytes(1000), "1.0kB");
-        assert_eq!(format_bytes(1076), "1.1kB");
-        assert_eq!(format_bytes(1048574), "1.0MB");
-        assert_eq!(format_bytes(1048578), "1.0MB");
-        assert_eq!(format_bytes(16043212), "15.3MB");
-        assert_eq!(format_bytes(702227152896), "654.0GB");
-        assert_eq!(format_bytes(1039475162591213420), "923.2PB");
-        assert_eq!(format_bytes(1503947516259121342), "1335.8PB");
+/// Draws a core's clock as a share of its highest along `arc`.
+fn frequency_arc(
+    ctx: &cairo::Context,
+    animator: &mut Animator,
+    core: &CoreSample,
+    arc: CoreArc,
+) -> Result<()> {
+    let Some(fraction) = core.frequency_fraction() else {
+        return Ok(());
+    };
+    let fraction = animator.value(&format!("{} frequency", core.name), fraction);
+    set_frequency_source(ctx, core);
+    arc.add(ctx, 0., fraction);
+    ctx.stroke()?;
+    Ok(())
+}
+
+/// Clocks are drawn in the warning color while the core is throttled.
+fn set_frequency_source(ctx: &cairo::Context, core: &CoreSample) {
+    if core.throttled {
+        let (red, green, blue) = Level::Warning.color(PINK);
+        ctx.set_source_rgb(red, green, blue);
+    } else {
+        ctx.set_source_rgba(1., 1., 1., 0.6);
     }
+}
+
+/// Normal color of a core's usage, which sets E-cores apart on hybrid CPUs.
+fn core_color(core: &CoreSample) -> (f64, f64, f64) {
+    match core.kind {
+        Some(CpuKind::Efficiency) => BLUE,
+        _ => PINK,
+    }
+}
+
+/// Draws titled lists of processes with their name, PID and a value right-aligned at `width`,
+/// growing upward from `bottom_y`. Empty lists are left out.
+fn process_list(
+    lists: &[(&str, &[(&ProcessSample, String)])],
+    x: f64,
+    bottom_y: f64,
+    width: f64,
+    ctx: &cairo::Context,
+) -> Result<()> {
+    let lists = lists
+        .iter()
+        .filter(|(_, rows)| !rows.is_empty())
+        .collect_vec();
+    let rows = lists.iter().map(|(_, rows)| rows.len() + 1).sum::<usize>();
+    let mut y = bottom_y - rows.saturating_sub(1) as f64 * SENSOR_ROW_HEIGHT;
+
+    ctx.set_source_rgba(1., 1., 1., 0.6);
+    ctx.set_font_size(10.);
+    for (title, rows) in lists {
+        ctx.move_to(x, y);
+        ctx.show_text(title)?;
+        y += SENSOR_ROW_HEIGHT;
+        for (process, value) in rows.iter() {
+            let name = if process.name.chars().count() > 14 {
+                format!("{}…", process.name.chars().take(13).collect::<String>())
+            } else {
+                process.name.clone()
+            };
+            ctx.move_to(x, y);
+            ctx.show_text(&name)?;
+            ctx.move_to(x + 100., y);
+            ctx.show_text(&process.pid.to_string())?;
+            ctx.move_to(x + width - ctx.text_extents(value)?.width(), y);
+            ctx.show_text(value)?;
+            y += SENSOR_ROW_HEIGHT;
+        }
+    }
+    Ok(())
+}
 
-    #[test]
-    fn test_push_within_limit() {
-        let mut nums: VecDeque<i32> = Default::default();
-        push_within_limit(&mut nums, 5, 3);
-        assert_eq!(nums.iter().collect_vec(), vec![&5]);
-        push_within_limit(&mut nums, 6, 3);
-        push_within_limit(&mut nums, 7, 3);
-        assert_eq!(nums.iter().collect_vec(), vec![&7, &6, &5]);
-        push_within_limit(&mut nums, 8, 3);
-        assert_eq!(nums.iter().collect_vec(), vec![&8, &7, &6]);
+/// Draws the readings of `values` over `window` as a thin line in the current source, scaled
+/// to their own range so small changes stay visible. `y` is the baseline of its text row.
+fn sparkline(
+    values: &History,
+    window: TimeWindow,
+    origin_x: f64,
+    y: f64,
+    ctx: &cairo::Context,
+) -> Result<()> {
+    let averages = values.window(window).map(|bucket| bucket.avg).collect_vec();
+    if averages.len() < 2 {
+        return Ok(());
     }
+    let (min, max) = averages
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+    let range = (max - min).max(1.);
+    let step = SPARKLINE_LENGTH / values.points(window) as f64;
+    let (bottom, height) = (y, 8.);
+    let points = averages
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            (
+                origin_x + SPARKLINE_LENGTH - i as f64 * step,
+                bottom - (v - min) / range * height,
+            )
+        })
+        .collect_vec();
+
+    ctx.set_line_width(1.);
+    ctx.new_path();
+    graph::trace(ctx, &points, false, bottom - height, bottom);
+    ctx.stroke()?;
+    Ok(())
 }
@@ -0,0 +1,212 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::sample::Usage;
+
+/// Where RAM went, in bytes. Everything but `available` adds up to `total` together with free
+/// memory, the way `free` splits it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct MemoryBreakdown {
+    pub total: u64,
+    /// Taken by processes and the kernel, and not counted below.
+    pub used: u64,
+    /// tmpfs and shared memory segments.
+    pub shared: u64,
+    pub buffers: u64,
+    /// Page cache and reclaimable kernel caches, not counting shared memory.
+    pub cache: u64,
+    /// Reserved for huge pages, whether or not they're in use.
+    pub huge_pages: u64,
+    /// What could be handed out without swapping, including most of the cache.
+    pub available: u64,
+}
+
+impl MemoryBreakdown {
+    pub const SEGMENTS: [&str; 5] = ["used", "shared", "buffers", "cache", "huge"];
+
+    /// Amounts in the order of `SEGMENTS`, which is how the memory pill stacks them.
+    pub fn segments(&self) -> [u64; 5] {
+        [
+            self.used,
+            self.shared,
+            self.buffers,
+            self.cache,
+            self.huge_pages,
+        ]
+    }
+}
+
+/// Swap split by whether it's held compressed in RAM or written to disk, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct SwapBreakdown {
+    /// Swap on zram devices.
+    pub zram: Usage,
+    /// Swap files and partitions.
+    pub disk: Usage,
+    /// Pages of disk swap that zswap keeps compressed in RAM instead of writing them out.
+    pub zswapped: u64,
+}
+
+impl SwapBreakdown {
+    /// Used swap that is in RAM rather than on disk.
+    pub fn in_ram(&self) -> u64 {
+        self.zram.used + self.zswapped.min(self.disk.used)
+    }
+}
+
+/// Reads the breakdown of RAM from `/proc/meminfo` and of swap from `/proc/swaps`.
+pub struct MemInfo {
+    meminfo: PathBuf,
+    swaps: PathBuf,
+}
+
+impl MemInfo {
+    /// Reads `meminfo` and `swaps` instead of the files in `/proc`, e.g. test fixtures.
+    pub fn new(meminfo: impl Into<PathBuf>, swaps: impl Into<PathBuf>) -> Self {
+        MemInfo {
+            meminfo: meminfo.into(),
+            swaps: swaps.into(),
+        }
+    }
+
+    /// `None` if `/proc/meminfo` can't be read, e.g. off Linux. Swap is empty without
+    /// `/proc/swaps`.
+    pub fn read(&self) -> Option<(MemoryBreakdown, SwapBreakdown)> {
+        let contents = fs::read_to_string(&self.meminfo).ok()?;
+        // Every field but the huge page counts is in kB.
+        let fields = contents
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                let mut value = value.split_whitespace();
+                let number = value.next()?.parse::<u64>().ok()?;
+                let scale = if value.next() == Some("kB") { 1024 } else { 1 };
+                Some((name, number * scale))
+            })
+            .collect::<HashMap<_, _>>();
+        let field = |name: &str| fields.get(name).copied().unwrap_or_default();
+
+        let total = *fields.get("MemTotal")?;
+        let shared = field("Shmem");
+        // Shared memory lives in the page cache, but can't be dropped like the rest of it.
+        let cache = (field("Cached") + field("SReclaimable")).saturating_sub(shared);
+        let huge_pages = fields
+            .get("Hugetlb")
+            .copied()
+            .unwrap_or_else(|| field("HugePages_Total") * field("Hugepagesize"));
+        let buffers = field("Buffers");
+        let used = total
+            .saturating_sub(field("MemFree"))
+            .saturating_sub(buffers)
+            .saturating_sub(cache + shared)
+            .saturating_sub(huge_pages);
+        let memory = MemoryBreakdown {
+            total,
+            used,
+            shared,
+            buffers,
+            cache,
+            huge_pages,
+            available: fields
+                .get("MemAvailable")
+                .copied()
+                .unwrap_or_else(|| field("MemFree") + buffers + cache),
+        };
+
+        let mut swap = SwapBreakdown {
+            zswapped: field("Zswapped"),
+            ..SwapBreakdown::default()
+        };
+        let swaps = fs::read_to_string(&self.swaps).unwrap_or_default();
+        // Filename, type, size and used in KiB, and priority, under a header line.
+        for line in swaps.lines().skip(1) {
+            let columns = line.split_whitespace().collect::<Vec<_>>();
+            let [name, _, size, used, ..] = columns[..] else {
+                continue;
+            };
+            let (Ok(size), Ok(used)) = (size.parse::<u64>(), used.parse::<u64>()) else {
+                continue;
+            };
+            let device = if name.starts_with("/dev/zram") {
+                &mut swap.zram
+            } else {
+                &mut swap.disk
+            };
+            device.total += size * 1024;
+            device.used += used * 1024;
+        }
+        Some((memory, swap))
+    }
+}
+
+impl Default for MemInfo {
+    fn default() -> Self {
+        MemInfo::new("/proc/meminfo", "/proc/swaps")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_meminfo() {
+        let dir = tempfile::tempdir().unwrap();
+        let (meminfo, swaps) = (dir.path().join("meminfo"), dir.path().join("swaps"));
+        let info = MemInfo::new(&meminfo, &swaps);
+        assert_eq!(info.read(), None);
+
+        fs::write(
+            &meminfo,
+            "MemTotal:        1000 kB\n\
+             MemFree:          100 kB\n\
+             MemAvailable:     500 kB\n\
+             Buffers:           50 kB\n\
+             Cached:           300 kB\n\
+             Zswapped:          20 kB\n\
+             Shmem:             40 kB\n\
+             SReclaimable:      10 kB\n\
+             HugePages_Total:    2\n\
+             Hugepagesize:      64 kB\n",
+        )
+        .unwrap();
+        let (memory, swap) = info.read().unwrap();
+        assert_eq!(
+            memory,
+            MemoryBreakdown {
+                total: 1000 * 1024,
+                used: 412 * 1024,
+                shared: 40 * 1024,
+                buffers: 50 * 1024,
+                cache: 270 * 1024,
+                huge_pages: 128 * 1024,
+                available: 500 * 1024,
+            }
+        );
+        assert_eq!(
+            memory.segments().iter().sum::<u64>() + 100 * 1024,
+            memory.total
+        );
+        // No swap at all without `/proc/swaps`.
+        assert_eq!(swap.in_ram(), 0);
+
+        fs::write(
+            &swaps,
+            "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+             /dev/zram0                              partition\t4096\t\t1024\t\t100\n\
+             /swapfile                               file\t\t8192\t\t30\t\t-2\n",
+        )
+        .unwrap();
+        let (_, swap) = info.read().unwrap();
+        assert_eq!(
+            swap.zram,
+            Usage {
+                used: 1024 * 1024,
+                total: 4096 * 1024
+            }
+        );
+        assert_eq!(swap.disk.total, 8192 * 1024);
+        assert_eq!(swap.in_ram(), (1024 + 20) * 1024);
+    }
+}
@@ -0,0 +1,620 @@
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    io::{self, BufRead as _, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context as _, Result};
+use itertools::Itertools as _;
+use log::{debug, error, info};
+
+use crate::{
+    cpu_time::CpuBreakdown,
+    ipc::{self, SocketFile},
+    meminfo::MemoryBreakdown,
+    sample::Sample,
+    sensors::SensorKind,
+};
+
+/// Upper bounds of the render time histogram buckets, in seconds.
+const RENDER_BUCKETS: [f64; 8] = [0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.25];
+/// Scrapers that stall mid-request get disconnected after this long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where to serve `/metrics`: `localhost:9101` or `unix:/run/user/1000/widget-metrics.sock`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_prefix("unix:") {
+            Some(path) => Ok(ListenAddr::Unix(path.into())),
+            None => s
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .map(ListenAddr::Tcp)
+                .with_context(|| {
+                    format!("Invalid address {s:?}, expected e.g. localhost:9101 or unix:<path>")
+                }),
+        }
+    }
+}
+
+/// Cumulative histogram in the shape Prometheus expects.
+#[derive(Debug, Default)]
+struct Histogram {
+    counts: [u64; RENDER_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bound, count) in RENDER_BUCKETS.iter().zip(&mut self.counts) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// The latest sample and the widget's own timings, shared with the HTTP listener.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    sample: Sample,
+    sample_duration: Duration,
+    render_time: Histogram,
+}
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+impl Metrics {
+    pub fn record_sample(&mut self, sample: &Sample, duration: Duration) {
+        self.sample = sample.clone();
+        self.sample_duration = duration;
+    }
+
+    pub fn record_render(&mut self, duration: Duration) {
+        self.render_time.observe(duration.as_secs_f64());
+    }
+
+    /// Everything in the Prometheus text exposition format.
+    pub fn exposition(&self) -> String {
+        let sample = &self.sample;
+        let mut out = Exposition::default();
+
+        // Metrics the machine doesn't have are left out rather than reported as 0.
+        if let Some(cpu) = sample.cpu {
+            out.family(
+                "widget_cpu_usage_percent",
+                "gauge",
+                "Average usage of all cores.",
+            );
+            out.value(&[], cpu);
+        }
+        out.family(
+            "widget_cpu_core_usage_percent",
+            "gauge",
+            "Usage of each core.",
+        );
+        for core in &sample.cores {
+            out.value(&[("core", &core.name)], core.usage);
+        }
+        if sample.cores.iter().any(|core| core.frequency.is_some()) {
+            out.family(
+                "widget_cpu_core_frequency_mhz",
+                "gauge",
+                "Current clock of each core.",
+            );
+            for core in &sample.cores {
+                if let Some(frequency) = core.frequency {
+                    out.value(&[("core", &core.name)], frequency);
+                }
+            }
+        }
+        out.family(
+            "widget_cpu_throttled",
+            "gauge",
+            "Whether any core was throttled for running hot since the previous sample.",
+        );
+        out.value(
+            &[],
+            sample.cores.iter().any(|core| core.throttled) as u8 as f64,
+        );
+        if let Some(breakdown) = &sample.cpu_breakdown {
+            out.family(
+                "widget_cpu_mode_percent",
+                "gauge",
+                "Share of all cores' time spent in each mode.",
+            );
+            for (mode, percent) in CpuBreakdown::MODES.into_iter().zip(breakdown.values()) {
+                out.value(&[("mode", mode)], percent);
+            }
+        }
+
+        for (name, usage) in [("memory", sample.memory), ("swap", sample.swap)] {
+            let Some(usage) = usage else {
+                continue;
+            };
+            out.family(
+                &format!("widget_{name}_used_bytes"),
+                "gauge",
+                &format!("Used {name}."),
+            );
+            out.value(&[], usage.used as f64);
+            out.family(
+                &format!("widget_{name}_total_bytes"),
+                "gauge",
+                &format!("Total {name}."),
+            );
+            out.value(&[], usage.total as f64);
+        }
+        if let Some(memory) = &sample.memory_breakdown {
+            out.family(
+                "widget_memory_breakdown_bytes",
+                "gauge",
+                "Memory by what it's used for, with available memory overlapping the cache.",
+            );
+            for (segment, bytes) in MemoryBreakdown::SEGMENTS
+                .into_iter()
+                .zip(memory.segments())
+                .chain([("available", memory.available)])
+            {
+                out.value(&[("segment", segment)], bytes as f64);
+            }
+        }
+        if let Some(swap) = &sample.swap_breakdown {
+            out.family(
+                "widget_swap_breakdown_bytes",
+                "gauge",
+                "Used swap by whether it's compressed in RAM or written to disk.",
+            );
+            let in_ram = swap.in_ram();
+            let used = swap.zram.used + swap.disk.used;
+            out.value(&[("location", "ram")], in_ram as f64);
+            out.value(&[("location", "disk")], used.saturating_sub(in_ram) as f64);
+        }
+
+        out.family(
+            "widget_disk_used_bytes",
+            "gauge",
+            "Used space of each mounted disk.",
+        );
+        for disk in &sample.disks {
+            let mount_point = disk.mount_point.to_string_lossy();
+            let labels = [
+                ("device", disk.name.as_str()),
+                ("mount_point", &mount_point),
+            ];
+            out.value(&labels, disk.space.used as f64);
+        }
+        out.family(
+            "widget_disk_total_bytes",
+            "gauge",
+            "Size of each mounted disk.",
+        );
+        for disk in &sample.disks {
+            let mount_point = disk.mount_point.to_string_lossy();
+            let labels = [
+                ("device", disk.name.as_str()),
+                ("mount_point", &mount_point),
+            ];
+            out.value(&labels, disk.space.total as f64);
+        }
+
+        // Several mounts can share a device, whose I/O must only be counted once.
+        let mut seen = HashSet::new();
+        let devices = sample
+            .disks
+            .iter()
+            .filter(|disk| seen.insert(&disk.name))
+            .collect_vec();
+        out.family(
+            "widget_disk_read_bytes_total",
+            "counter",
+            "Bytes read from each disk device.",
+        );
+        for disk in &devices {
+            out.value(&[("device", &disk.name)], disk.read_bytes as f64);
+        }
+        out.family(
+            "widget_disk_written_bytes_total",
+            "counter",
+            "Bytes written to each disk device.",
+        );
+        for disk in &devices {
+            out.value(&[("device", &disk.name)], disk.written_bytes as f64);
+        }
+
+        out.family(
+            "widget_network_received_bytes_total",
+            "counter",
+            "Bytes received on each network interface.",
+        );
+        for interface in &sample.interfaces {
+            out.value(
+                &[("interface", &interface.name)],
+                interface.received_bytes as f64,
+            );
+        }
+        out.family(
+            "widget_network_transmitted_bytes_total",
+            "counter",
+            "Bytes sent on each network interface.",
+        );
+        for interface in &sample.interfaces {
+            out.value(
+                &[("interface", &interface.name)],
+                interface.transmitted_bytes as f64,
+            );
+        }
+
+        if let Some(load) = sample.load {
+            out.family(
+                "widget_load_average",
+                "gauge",
+                "Average number of runnable processes over the period.",
+            );
+            for (period, load) in [("1m", load.one), ("5m", load.five), ("15m", load.fifteen)] {
+                out.value(&[("period", period)], load);
+            }
+        }
+
+        for (kind, name, help) in [
+            (
+                SensorKind::Temperature,
+                "widget_temperature_celsius",
+                "Temperature of each hwmon sensor.",
+            ),
+            (
+                SensorKind::Fan,
+                "widget_fan_rpm",
+                "Speed of each hwmon fan.",
+            ),
+        ] {
+            out.family(name, "gauge", help);
+            for reading in sample.sensors.iter().filter(|r| r.kind == kind) {
+                out.value(&[("sensor", &reading.label)], reading.value);
+            }
+        }
+
+        if let Some(battery) = &sample.battery {
+            out.family(
+                "widget_battery_charge_percent",
+                "gauge",
+                "Charge of all batteries combined.",
+            );
+            out.value(&[], battery.charge);
+            if let Some(power) = battery.power {
+                out.family(
+                    "widget_battery_power_watts",
+                    "gauge",
+                    "Power flowing into or out of the batteries.",
+                );
+                out.value(&[], power);
+            }
+        }
+
+        for (name, rate, help) in [
+            ("disk_read", sample.disk_read, "read from all disks"),
+            ("disk_write", sample.disk_write, "written to all disks"),
+            (
+                "network_receive",
+                sample.download,
+                "received on all interfaces",
+            ),
+            ("network_transmit", sample.upload, "sent on all interfaces"),
+        ] {
+            let Some(rate) = rate else {
+                continue;
+            };
+            out.family(
+                &format!("widget_{name}_bytes_per_second"),
+                "gauge",
+                &format!("Bytes per second {help} during the last sample interval."),
+            );
+            out.value(&[], rate);
+        }
+
+        out.family(
+            "widget_sample_duration_seconds",
+            "gauge",
+            "Time taken by the latest sample.",
+        );
+        out.value(&[], self.sample_duration.as_secs_f64());
+
+        let render = &self.render_time;
+        out.family(
+            "widget_render_duration_seconds",
+            "histogram",
+            "Time taken to draw and submit each frame.",
+        );
+        for (bound, count) in RENDER_BUCKETS.iter().zip(render.counts) {
+            out.sample(
+                "widget_render_duration_seconds_bucket",
+                &[("le", &bound.to_string())],
+                count as f64,
+            );
+        }
+        out.sample(
+            "widget_render_duration_seconds_bucket",
+            &[("le", "+Inf")],
+            render.count as f64,
+        );
+        out.sample("widget_render_duration_seconds_sum", &[], render.sum);
+        out.sample(
+            "widget_render_duration_seconds_count",
+            &[],
+            render.count as f64,
+        );
+        out.text
+    }
+}
+
+#[derive(Default)]
+struct Exposition {
+    text: String,
+    family: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {name} {help}");
+        let _ = writeln!(self.text, "# TYPE {name} {kind}");
+        self.family = name.to_owned();
+    }
+
+    /// Adds a sample of the current family.
+    fn value(&mut self, labels: &[(&str, &str)], value: f64) {
+        let name = std::mem::take(&mut self.family);
+        self.sample(&name, labels, value);
+        self.family = name;
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(key, value)| format!("{key}=\"{}\"", escape_label(value)))
+                .join(",");
+            let _ = write!(self.text, "{{{labels}}}");
+        }
+        let _ = writeln!(self.text, " {value}");
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serves `/metrics` at `addr` from a background thread. A unix socket is removed again when
+/// the returned `SocketFile` is dropped.
+pub fn serve(addr: &ListenAddr, metrics: SharedMetrics) -> Result<Option<SocketFile>> {
+    match addr {
+        ListenAddr::Tcp(addr) => {
+            let listener =
+                TcpListener::bind(addr).with_context(|| format!("Failed to bind {addr}"))?;
+            info!("Serving metrics on http://{addr}/metrics");
+            spawn_server(move || listener.accept().map(|(stream, _)| stream), metrics);
+            Ok(None)
+        }
+        ListenAddr::Unix(path) => {
+            let (listener, socket) = ipc::bind(path)?;
+            info!("Serving metrics on {}", path.display());
+            spawn_server(move || listener.accept().map(|(stream, _)| stream), metrics);
+            Ok(Some(socket))
+        }
+    }
+}
+
+/// A scraper's connection, over TCP or a unix socket.
+trait Connection: Read + Write {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Connection for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Answers the connections `accept` waits for, one at a time, from a background thread.
+fn spawn_server<C: Connection>(
+    mut accept: impl FnMut() -> io::Result<C> + Send + 'static,
+    metrics: SharedMetrics,
+) {
+    thread::spawn(move || {
+        loop {
+            let result = accept().and_then(|stream| {
+                stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+                handle(stream, &metrics)
+            });
+            if let Err(e) = result {
+                debug!("Metrics request failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Answers one HTTP request, closing the connection afterwards.
+fn handle<S>(stream: S, metrics: &Mutex<Metrics>) -> io::Result<()>
+where
+    S: Read + Write,
+{
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, there's nothing in them we need.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => match metrics.lock() {
+            Ok(metrics) => ("200 OK", metrics.exposition()),
+            Err(_) => {
+                error!("Metrics are unavailable after a panic");
+                ("500 Internal Server Error", String::new())
+            }
+        },
+        (Some("GET"), _) => ("404 Not Found", "Metrics are at /metrics\n".to_owned()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use super::*;
+    use crate::{
+        sample::{CoreSample, DiskSample, InterfaceSample, Usage},
+        sensors::SensorReading,
+    };
+
+    fn metrics() -> Metrics {
+        let disk = |mount_point: &str| DiskSample {
+            name: "/dev/vda".to_owned(),
+            mount_point: mount_point.into(),
+            space: Usage { used: 1, total: 2 },
+            read_bytes: 300,
+            written_bytes: 400,
+        };
+        let sample = Sample {
+            cpu: Some(12.5),
+            cores: vec![CoreSample {
+                name: "cpu0".to_owned(),
+                usage: 12.5,
+                breakdown: None,
+                kind: None,
+                frequency: Some(800.),
+                max_frequency: None,
+                throttled: false,
+            }],
+            cpu_breakdown: Some(CpuBreakdown {
+                user: 10.,
+                iowait: 2.5,
+                ..CpuBreakdown::default()
+            }),
+            memory: Usage::new(1, 4),
+            memory_breakdown: Some(MemoryBreakdown {
+                total: 4,
+                used: 1,
+                cache: 2,
+                ..MemoryBreakdown::default()
+            }),
+            disks: vec![disk("/"), disk("/home \"quoted\"")],
+            interfaces: vec![InterfaceSample {
+                name: "eth0".to_owned(),
+                received_bytes: 5,
+                transmitted_bytes: 6,
+            }],
+            sensors: vec![SensorReading {
+                label: "coretemp Package id 0".to_owned(),
+                kind: SensorKind::Temperature,
+                value: 45.,
+                critical: Some(100.),
+            }],
+            ..Sample::default()
+        };
+        let mut metrics = Metrics::default();
+        metrics.record_sample(&sample, Duration::from_millis(3));
+        metrics.record_render(Duration::from_millis(4));
+        metrics.record_render(Duration::from_millis(40));
+        metrics
+    }
+
+    #[test]
+    fn test_exposition() {
+        let text = metrics().exposition();
+        let lines = text.lines().collect_vec();
+        for expected in [
+            "# TYPE widget_cpu_core_usage_percent gauge",
+            "widget_cpu_core_usage_percent{core=\"cpu0\"} 12.5",
+            "widget_cpu_core_frequency_mhz{core=\"cpu0\"} 800",
+            "widget_cpu_throttled 0",
+            "widget_cpu_mode_percent{mode=\"iowait\"} 2.5",
+            "widget_memory_total_bytes 4",
+            "widget_memory_breakdown_bytes{segment=\"cache\"} 2",
+            "widget_disk_used_bytes{device=\"/dev/vda\",mount_point=\"/home \\\"quoted\\\"\"} 1",
+            "widget_network_received_bytes_total{interface=\"eth0\"} 5",
+            "widget_temperature_celsius{sensor=\"coretemp Package id 0\"} 45",
+            "widget_sample_duration_seconds 0.003",
+            "widget_render_duration_seconds_bucket{le=\"0.005\"} 1",
+            "widget_render_duration_seconds_bucket{le=\"+Inf\"} 2",
+            "widget_render_duration_seconds_count 2",
+        ] {
+            assert!(lines.contains(&expected), "missing {expected:?} in\n{text}");
+        }
+        // The device is only counted once even though it's mounted twice.
+        let reads = lines
+            .iter()
+            .filter(|line| line.starts_with("widget_disk_read_bytes_total{"))
+            .count();
+        assert_eq!(reads, 1);
+        // There's no swap or network traffic to report, rather than zeros.
+        assert!(!text.contains("widget_swap_total_bytes"));
+        assert!(!text.contains("widget_network_receive_bytes_per_second"));
+    }
+
+    #[test]
+    fn test_serve_request() {
+        let metrics = Mutex::new(metrics());
+        let request = |request: &str| {
+            let (mut client, server) = UnixStream::pair().unwrap();
+            client.write_all(request.as_bytes()).unwrap();
+            handle(&server, &metrics).unwrap();
+            drop(server);
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = request("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\n\r\n# HELP widget_cpu_usage_percent"));
+        assert!(request("GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 Not Found"));
+        assert_eq!(
+            "unix:/tmp/metrics.sock".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Unix("/tmp/metrics.sock".into())
+        );
+        assert!("localhost".parse::<ListenAddr>().is_err());
+    }
+}
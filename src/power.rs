@@ -0,0 +1,253 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use itertools::Itertools as _;
+use serde::Serialize;
+
+use crate::threshold::Level;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChargeState {
+    Charging,
+    Discharging,
+    Full,
+    /// Plugged in but held below full, e.g. by a charge limit.
+    #[serde(rename = "not charging")]
+    NotCharging,
+    Unknown,
+}
+
+impl ChargeState {
+    fn parse(status: &str) -> Self {
+        match status {
+            "Charging" => ChargeState::Charging,
+            "Discharging" => ChargeState::Discharging,
+            "Full" => ChargeState::Full,
+            "Not charging" => ChargeState::NotCharging,
+            _ => ChargeState::Unknown,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ChargeState::Charging => "charging",
+            ChargeState::Discharging => "discharging",
+            ChargeState::Full => "full",
+            ChargeState::NotCharging => "not charging",
+            ChargeState::Unknown => "unknown",
+        }
+    }
+}
+
+/// The system's batteries taken together.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BatterySample {
+    /// Percent.
+    pub charge: f64,
+    pub state: ChargeState,
+    /// Watts flowing into or out of the batteries, if they report it.
+    pub power: Option<f64>,
+    /// Watt-hours stored now and when full, if they report it.
+    pub energy: Option<f64>,
+    pub energy_full: Option<f64>,
+}
+
+impl BatterySample {
+    /// Time until the batteries are empty or full at an average draw of `power` watts, while
+    /// discharging or charging respectively.
+    pub fn time_remaining(&self, power: f64) -> Option<Duration> {
+        if power <= 0. {
+            return None;
+        }
+        let watt_hours = match self.state {
+            ChargeState::Charging => self.energy_full? - self.energy?,
+            ChargeState::Discharging => self.energy?,
+            _ => return None,
+        };
+        Some(Duration::from_secs_f64(watt_hours.max(0.) / power * 3600.))
+    }
+
+    /// Only a draining battery is a concern: it's a warning at 20% and critical at 10%.
+    pub fn level(&self) -> Level {
+        match self.state {
+            ChargeState::Discharging if self.charge <= 10. => Level::Critical,
+            ChargeState::Discharging if self.charge <= 20. => Level::Warning,
+            _ => Level::Normal,
+        }
+    }
+}
+
+/// Reads batteries from the kernel's power supply class.
+pub struct PowerSupply {
+    root: PathBuf,
+}
+
+impl PowerSupply {
+    /// Reads from `root` instead of `/sys/class/power_supply`, e.g. a directory of test fixtures.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        PowerSupply { root: root.into() }
+    }
+
+    /// All system batteries combined, or `None` if there are none. Batteries of peripherals
+    /// like mice and AC adapters are ignored.
+    pub fn read(&self) -> Option<BatterySample> {
+        let batteries = fs::read_dir(&self.root)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .sorted()
+            .filter_map(|path| Battery::read(&path))
+            .collect_vec();
+        if batteries.is_empty() {
+            return None;
+        }
+
+        let states = batteries.iter().map(|b| b.state).collect_vec();
+        let state = if states.contains(&ChargeState::Charging) {
+            ChargeState::Charging
+        } else if states.contains(&ChargeState::Discharging) {
+            ChargeState::Discharging
+        } else if states.iter().all(|&s| s == ChargeState::Full) {
+            ChargeState::Full
+        } else {
+            states[0]
+        };
+        let sum = |field: fn(&Battery) -> Option<f64>| -> Option<f64> {
+            batteries.iter().map(field).sum()
+        };
+        let energy = sum(|b| b.energy);
+        let energy_full = sum(|b| b.energy_full);
+        let charge = match (energy, energy_full) {
+            (Some(energy), Some(full)) if full > 0. => 100. * energy / full,
+            _ => batteries.iter().map(|b| b.capacity).sum::<f64>() / batteries.len() as f64,
+        };
+        Some(BatterySample {
+            charge: charge.clamp(0., 100.),
+            state,
+            power: sum(|b| b.power),
+            energy,
+            energy_full,
+        })
+    }
+}
+
+impl Default for PowerSupply {
+    fn default() -> Self {
+        PowerSupply::new("/sys/class/power_supply")
+    }
+}
+
+/// One battery, in watts and watt-hours.
+struct Battery {
+    capacity: f64,
+    state: ChargeState,
+    power: Option<f64>,
+    energy: Option<f64>,
+    energy_full: Option<f64>,
+}
+
+impl Battery {
+    fn read(path: &Path) -> Option<Self> {
+        let read = |file: &str| {
+            fs::read_to_string(path.join(file))
+                .ok()
+                .map(|value| value.trim().to_owned())
+        };
+        // Sysfs reports micro-units: µW, µWh, µA, µAh and µV.
+        let micro = |file: &str| Some(read(file)?.parse::<f64>().ok()? / 1e6);
+
+        if read("type")? != "Battery" || read("scope").as_deref() == Some("Device") {
+            return None;
+        }
+        if read("present").as_deref() == Some("0") {
+            return None;
+        }
+
+        // Batteries report either energy and power or charge and current, which are converted
+        // with the voltage.
+        let voltage = micro("voltage_now");
+        let design_voltage = micro("voltage_min_design").or(voltage);
+        let power = micro("power_now").or_else(|| Some(micro("current_now")? * voltage?));
+        let energy = micro("energy_now").or_else(|| Some(micro("charge_now")? * design_voltage?));
+        let energy_full =
+            micro("energy_full").or_else(|| Some(micro("charge_full")? * design_voltage?));
+
+        Some(Battery {
+            capacity: read("capacity")?.parse().ok()?,
+            state: read("status").map_or(ChargeState::Unknown, |s| ChargeState::parse(&s)),
+            power: power.map(f64::abs),
+            energy,
+            energy_full,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::write;
+
+    #[test]
+    fn test_read_power_supply() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "AC/type", "Mains\n");
+        write(root, "AC/online", "0\n");
+        write(root, "BAT0/type", "Battery\n");
+        write(root, "BAT0/status", "Discharging\n");
+        write(root, "BAT0/capacity", "60\n");
+        write(root, "BAT0/energy_now", "30000000\n");
+        write(root, "BAT0/energy_full", "50000000\n");
+        write(root, "BAT0/power_now", "10000000\n");
+        // Reports charge and current instead, and is idle while the other one discharges.
+        write(root, "BAT1/type", "Battery\n");
+        write(root, "BAT1/status", "Not charging\n");
+        write(root, "BAT1/capacity", "20\n");
+        write(root, "BAT1/charge_now", "1000000\n");
+        write(root, "BAT1/charge_full", "5000000\n");
+        write(root, "BAT1/current_now", "0\n");
+        write(root, "BAT1/voltage_now", "10000000\n");
+        write(root, "hidpp_battery_0/type", "Battery\n");
+        write(root, "hidpp_battery_0/scope", "Device\n");
+        write(root, "hidpp_battery_0/capacity", "5\n");
+
+        let battery = PowerSupply::new(root).read().unwrap();
+        assert_eq!(
+            battery,
+            BatterySample {
+                charge: 40.,
+                state: ChargeState::Discharging,
+                power: Some(10.),
+                energy: Some(40.),
+                energy_full: Some(100.),
+            }
+        );
+        assert_eq!(
+            battery.time_remaining(10.),
+            Some(Duration::from_secs(4 * 60 * 60))
+        );
+        assert_eq!(battery.time_remaining(0.), None);
+        assert_eq!(battery.level(), Level::Normal);
+
+        let charging = BatterySample {
+            state: ChargeState::Charging,
+            ..battery.clone()
+        };
+        assert_eq!(
+            charging.time_remaining(30.),
+            Some(Duration::from_secs(2 * 60 * 60))
+        );
+        let draining = BatterySample {
+            charge: 8.,
+            ..battery
+        };
+        assert_eq!(draining.level(), Level::Critical);
+
+        fs::remove_dir_all(root.join("BAT0")).unwrap();
+        fs::remove_dir_all(root.join("BAT1")).unwrap();
+        assert_eq!(PowerSupply::new(root).read(), None);
+    }
+}
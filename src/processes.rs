@@ -0,0 +1,135 @@
+use std::{
+    cmp::Ordering,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools as _;
+use serde::Serialize;
+use sysinfo::System;
+
+use crate::rate::{MAX_SAMPLE_GAP, RateCounters};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProcessSample {
+    pub pid: u32,
+    pub name: String,
+    /// Percent of all cores, like the overall CPU usage.
+    pub cpu: f64,
+    /// Resident memory in bytes.
+    pub memory: u64,
+    /// Bytes per second read from and written to disk since the previous walk of the process
+    /// list, 0 for processes that weren't around for it.
+    pub read_rate: f64,
+    pub write_rate: f64,
+}
+
+impl ProcessSample {
+    pub fn io_rate(&self) -> f64 {
+        self.read_rate + self.write_rate
+    }
+}
+
+/// Cumulative disk I/O of each process, keyed by PID and start time so a process that reuses
+/// the PID of one that exited starts from its own baseline.
+pub struct IoCounters {
+    read: RateCounters<(u32, u64)>,
+    write: RateCounters<(u32, u64)>,
+}
+
+impl IoCounters {
+    /// Counters for processes walked every `interval`, which can be much longer than the time
+    /// between samples.
+    pub fn new(interval: Duration) -> Self {
+        // Walks happen on the first sample after each interval, so they can be a bit further
+        // apart.
+        let max_gap = interval + MAX_SAMPLE_GAP;
+        IoCounters {
+            read: RateCounters::with_max_gap(max_gap),
+            write: RateCounters::with_max_gap(max_gap),
+        }
+    }
+}
+
+/// Every process `system` last refreshed at `now`.
+pub fn processes(system: &System, io: &mut IoCounters, now: Instant) -> Vec<ProcessSample> {
+    let cores = system.cpus().len().max(1) as f64;
+    let processes = system
+        .processes()
+        .iter()
+        .map(|(pid, process)| ((pid.as_u32(), process.start_time()), process))
+        .collect_vec();
+    // Processes we can't read the I/O of, like other users', count as not doing any.
+    let read_rates = io.read.rates(
+        now,
+        processes
+            .iter()
+            .map(|&(key, process)| (key, process.disk_usage().total_read_bytes)),
+    );
+    let write_rates = io.write.rates(
+        now,
+        processes
+            .iter()
+            .map(|&(key, process)| (key, process.disk_usage().total_written_bytes)),
+    );
+    processes
+        .iter()
+        .map(|&(key, process)| ProcessSample {
+            pid: key.0,
+            name: process.name().to_string_lossy().into_owned(),
+            cpu: process.cpu_usage() as f64 / cores,
+            memory: process.memory(),
+            read_rate: read_rates.get(&key).copied().unwrap_or_default(),
+            write_rate: write_rates.get(&key).copied().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// The `count` processes with the largest `key`, largest first. Ties go to the lower PID so the
+/// list doesn't shuffle between samples.
+pub fn top(
+    processes: &[ProcessSample],
+    count: usize,
+    key: impl Fn(&ProcessSample) -> f64,
+) -> Vec<ProcessSample> {
+    processes
+        .iter()
+        .sorted_by(|a, b| {
+            key(b)
+                .partial_cmp(&key(a))
+                .unwrap_or(Ordering::Equal)
+                .then(a.pid.cmp(&b.pid))
+        })
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_processes() {
+        let process = |pid, cpu, memory| ProcessSample {
+            pid,
+            name: format!("process{pid}"),
+            cpu,
+            memory,
+            read_rate: memory as f64,
+            write_rate: cpu,
+        };
+        let processes = [
+            process(1, 0.5, 300),
+            process(7, 12.5, 100),
+            process(3, 12.5, 200),
+            process(4, 40., 50),
+        ];
+
+        let pids = |top: Vec<ProcessSample>| top.iter().map(|p| p.pid).collect_vec();
+        assert_eq!(pids(top(&processes, 3, |p| p.cpu)), [4, 3, 7]);
+        assert_eq!(pids(top(&processes, 2, |p| p.memory as f64)), [1, 3]);
+        assert_eq!(pids(top(&processes, 2, ProcessSample::io_rate)), [1, 3]);
+        assert_eq!(pids(top(&processes, 10, |p| p.cpu)).len(), 4);
+        assert!(top(&[], 5, |p| p.cpu).is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Samples further apart than this (e.g. after the widget was stopped) restart the measurement
+/// instead of reporting an average over the whole gap.
+pub const MAX_SAMPLE_GAP: Duration = Duration::from_secs(30);
+
+/// Turns a cumulative counter, like total bytes read, into a per-second rate.
+#[derive(Default)]
+pub struct RateCounter {
+    last: Option<(Instant, u64)>,
+}
+
+impl RateCounter {
+    /// Records the counter's value at `now` and returns the rate since the previous call. There
+    /// is no rate for the first sample, after the counter went backwards (device reset or
+    /// replaced) or after a gap longer than `max_gap`.
+    pub fn update(&mut self, now: Instant, total: u64, max_gap: Duration) -> Option<f64> {
+        let (last_time, last_total) = self.last.replace((now, total))?;
+        let elapsed = now.checked_duration_since(last_time)?;
+        if elapsed.is_zero() || elapsed > max_gap || total < last_total {
+            return None;
+        }
+        Some((total - last_total) as f64 / elapsed.as_secs_f64())
+    }
+}
+
+/// Rate counters for a set of devices, reported as their combined rate. Tracking devices
+/// separately keeps one appearing or disappearing from showing up as a spike in the total.
+pub struct RateCounters<K> {
+    counters: HashMap<K, RateCounter>,
+    max_gap: Duration,
+}
+
+impl<K: Eq + Hash + Clone> RateCounters<K> {
+    pub fn new() -> Self {
+        RateCounters::with_max_gap(MAX_SAMPLE_GAP)
+    }
+
+    /// Counters read further apart than `MAX_SAMPLE_GAP` on purpose, which would otherwise never
+    /// have a rate.
+    pub fn with_max_gap(max_gap: Duration) -> Self {
+        RateCounters {
+            counters: HashMap::new(),
+            max_gap,
+        }
+    }
+
+    /// Records each device's cumulative counter and returns the summed rate in units per second,
+    /// or `None` if there are no devices at all. Devices missing from `totals` are forgotten.
+    pub fn update(
+        &mut self,
+        now: Instant,
+        totals: impl IntoIterator<Item = (K, u64)>,
+    ) -> Option<f64> {
+        let rates = self.rates(now, totals);
+        (!self.counters.is_empty()).then(|| rates.values().sum())
+    }
+
+    /// Like `update`, but returns the rate of each device that has one.
+    pub fn rates(
+        &mut self,
+        now: Instant,
+        totals: impl IntoIterator<Item = (K, u64)>,
+    ) -> HashMap<K, f64> {
+        let mut counters = HashMap::with_capacity(self.counters.len());
+        let mut rates = HashMap::new();
+        for (key, total) in totals {
+            let mut counter = self.counters.remove(&key).unwrap_or_default();
+            if let Some(rate) = counter.update(now, total, self.max_gap) {
+                rates.insert(key.clone(), rate);
+            }
+            counters.insert(key, counter);
+        }
+        self.counters = counters;
+        rates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_counter() {
+        let start = Instant::now();
+        let mut counter = RateCounter::default();
+        assert_eq!(counter.update(start, 1000, MAX_SAMPLE_GAP), None);
+        assert_eq!(
+            counter.update(start + Duration::from_secs(2), 3000, MAX_SAMPLE_GAP),
+            Some(1000.)
+        );
+        assert_eq!(
+            counter.update(start + Duration::from_millis(2500), 3500, MAX_SAMPLE_GAP),
+            Some(1000.)
+        );
+
+        // A reset counter yields no rate, then measures from the new baseline.
+        assert_eq!(
+            counter.update(start + Duration::from_secs(3), 100, MAX_SAMPLE_GAP),
+            None
+        );
+        assert_eq!(
+            counter.update(start + Duration::from_secs(4), 600, MAX_SAMPLE_GAP),
+            Some(500.)
+        );
+
+        // So does a sample taken after a long pause.
+        assert_eq!(
+            counter.update(start + Duration::from_secs(60), 9000, MAX_SAMPLE_GAP),
+            None
+        );
+        assert_eq!(
+            counter.update(start + Duration::from_secs(61), 9100, MAX_SAMPLE_GAP),
+            Some(100.)
+        );
+    }
+
+    #[test]
+    fn test_rate_counters() {
+        let start = Instant::now();
+        let mut counters = RateCounters::new();
+        assert_eq!(
+            counters.update(start, [("eth0", 100), ("wlan0", 0)]),
+            Some(0.)
+        );
+
+        let now = start + Duration::from_secs(1);
+        assert_eq!(
+            counters.update(now, [("eth0", 200), ("wlan0", 50)]),
+            Some(150.)
+        );
+
+        // A new interface with a large counter doesn't register as traffic.
+        let now = start + Duration::from_secs(2);
+        assert_eq!(
+            counters.update(now, [("eth0", 300), ("tun0", 1 << 40)]),
+            Some(100.)
+        );
+        let now = start + Duration::from_secs(3);
+        assert_eq!(
+            counters.rates(now, [("eth0", 300), ("tun0", (1 << 40) + 10)]),
+            HashMap::from([("eth0", 0.), ("tun0", 10.)])
+        );
+
+        // Without any devices there is no rate, rather than a rate of 0.
+        assert_eq!(counters.update(now, []), None);
+
+        let mut slow = RateCounters::with_max_gap(Duration::from_secs(60));
+        slow.update(start, [("nvme0n1", 0)]);
+        let now = start + Duration::from_secs(45);
+        assert_eq!(slow.update(now, [("nvme0n1", 900)]), Some(20.));
+    }
+}
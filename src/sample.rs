@@ -0,0 +1,435 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use itertools::Itertools as _;
+use serde::Serialize;
+use sysinfo::{Disks, Networks, ProcessesToUpdate, System};
+
+use crate::{
+    config::{Config, ProcessesConfig},
+    cpu_time::{CpuBreakdown, ProcStat},
+    cpufreq::CpuFreq,
+    meminfo::{MemInfo, MemoryBreakdown, SwapBreakdown},
+    power::{BatterySample, PowerSupply},
+    processes::{self, IoCounters, ProcessSample},
+    rate::RateCounters,
+    sensors::{Hwmon, SensorReading},
+    topology::{CpuKind, Topology, cpu_index},
+};
+
+/// Used and total amount of memory, swap or disk space, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub used: u64,
+    pub total: u64,
+}
+
+impl Usage {
+    /// `None` if there is nothing to use, like swap on a machine without any.
+    pub fn new(used: u64, total: u64) -> Option<Self> {
+        (total > 0).then_some(Usage { used, total })
+    }
+
+    /// Used share between 0 and 1, or `None` if the total is 0.
+    pub fn frac(&self) -> Option<f64> {
+        (self.total > 0).then(|| (self.used as f64 / self.total as f64).min(1.))
+    }
+
+    pub fn percent(&self) -> Option<f64> {
+        self.frac().map(|frac| 100. * frac)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CoreSample {
+    pub name: String,
+    /// Percent. Always known, as the cores themselves come from the usage sysinfo reports.
+    pub usage: f64,
+    /// What the core spent its time on, where `/proc/stat` is available.
+    pub breakdown: Option<CpuBreakdown>,
+    /// Whether it's a P-core or an E-core, on hybrid CPUs.
+    pub kind: Option<CpuKind>,
+    /// Current and highest clock in MHz, where the kernel or `/proc/cpuinfo` reports them.
+    pub frequency: Option<f64>,
+    pub max_frequency: Option<f64>,
+    /// Whether the core was throttled for running hot since the previous sample.
+    pub throttled: bool,
+}
+
+impl CoreSample {
+    /// Current clock as a fraction of the highest, if both are known.
+    pub fn frequency_fraction(&self) -> Option<f64> {
+        let max = self.max_frequency.filter(|&max| max > 0.)?;
+        Some((self.frequency? / max).clamp(0., 1.))
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DiskSample {
+    pub name: String,
+    pub mount_point: PathBuf,
+    #[serde(flatten)]
+    pub space: Usage,
+    /// Bytes transferred since boot, counted for the device rather than the mount.
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InterfaceSample {
+    pub name: String,
+    /// Bytes transferred since the interface came up.
+    pub received_bytes: u64,
+    pub transmitted_bytes: u64,
+}
+
+/// Average number of runnable processes over the last 1, 5 and 15 minutes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+impl LoadAverage {
+    /// Load as a percentage of `cores`, so 100 means every core was busy on average.
+    pub fn percent_of(self, cores: usize) -> LoadAverage {
+        let scale = 100. / cores.max(1) as f64;
+        LoadAverage {
+            one: self.one * scale,
+            five: self.five * scale,
+            fifteen: self.fifteen * scale,
+        }
+    }
+}
+
+/// Everything measured in one sample. Metrics that can't be measured on this machine are `None`
+/// or empty rather than 0.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Sample {
+    /// Average usage of all cores, in percent.
+    pub cpu: Option<f64>,
+    pub cores: Vec<CoreSample>,
+    /// What all cores together spent their time on, where `/proc/stat` is available.
+    pub cpu_breakdown: Option<CpuBreakdown>,
+    /// `None` where the system doesn't report a load average, which sysinfo signals with zeros.
+    pub load: Option<LoadAverage>,
+    /// `None` without any memory or swap to speak of.
+    pub memory: Option<Usage>,
+    pub swap: Option<Usage>,
+    /// Where memory and swap went, where `/proc/meminfo` is available.
+    pub memory_breakdown: Option<MemoryBreakdown>,
+    pub swap_breakdown: Option<SwapBreakdown>,
+    pub disks: Vec<DiskSample>,
+    pub interfaces: Vec<InterfaceSample>,
+    pub sensors: Vec<SensorReading>,
+    /// `None` on machines without a battery.
+    pub battery: Option<BatterySample>,
+    /// The busiest processes by CPU and by resident memory, busiest first. These are updated
+    /// less often than the rest of the sample.
+    pub top_cpu: Vec<ProcessSample>,
+    pub top_memory: Vec<ProcessSample>,
+    /// The processes reading and writing the most, by their combined rate.
+    pub top_io: Vec<ProcessSample>,
+    /// Bytes per second, summed over all disks or interfaces. `None` without any.
+    pub disk_read: Option<f64>,
+    pub disk_write: Option<f64>,
+    pub download: Option<f64>,
+    pub upload: Option<f64>,
+}
+
+impl Sample {
+    pub fn disk(&self, mount_point: impl AsRef<Path>) -> Option<&DiskSample> {
+        self.disks
+            .iter()
+            .find(|disk| disk.mount_point == mount_point.as_ref())
+    }
+}
+
+/// Owns the sysinfo handles and counters needed to take samples.
+pub struct Sampler {
+    system: System,
+    disks: Disks,
+    networks: Networks,
+    hwmon: Hwmon,
+    proc_stat: ProcStat,
+    power_supply: PowerSupply,
+    topology: Topology,
+    cpufreq: CpuFreq,
+    meminfo: MemInfo,
+    processes: ProcessesConfig,
+    /// When the process list is due to be refreshed again, and the lists it last produced.
+    next_processes: Option<Instant>,
+    top_cpu: Vec<ProcessSample>,
+    top_memory: Vec<ProcessSample>,
+    top_io: Vec<ProcessSample>,
+    process_io: IoCounters,
+    read_counters: RateCounters<OsString>,
+    write_counters: RateCounters<OsString>,
+    download_counters: RateCounters<String>,
+    upload_counters: RateCounters<String>,
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        Sampler {
+            system: System::new(),
+            disks: Disks::new(),
+            networks: Networks::new(),
+            hwmon: Hwmon::default(),
+            proc_stat: ProcStat::default(),
+            power_supply: PowerSupply::default(),
+            topology: Topology::default(),
+            cpufreq: CpuFreq::default(),
+            meminfo: MemInfo::default(),
+            processes: ProcessesConfig::default(),
+            next_processes: None,
+            top_cpu: Vec::new(),
+            top_memory: Vec::new(),
+            top_io: Vec::new(),
+            process_io: IoCounters::new(ProcessesConfig::default().interval.duration()),
+            read_counters: RateCounters::new(),
+            write_counters: RateCounters::new(),
+            download_counters: RateCounters::new(),
+            upload_counters: RateCounters::new(),
+        }
+    }
+
+    /// Applies the parts of `config` that affect sampling.
+    pub fn configure(&mut self, config: &Config) {
+        let old = std::mem::replace(&mut self.processes, config.processes);
+        if old.interval != self.processes.interval {
+            self.process_io = IoCounters::new(self.processes.interval.duration());
+        }
+        if old.interval != self.processes.interval || old.count < self.processes.count {
+            self.next_processes = None;
+        }
+        // Lists of the old length would otherwise stay around until the next walk, which never
+        // comes if the count is now 0.
+        let count = self.processes.count;
+        self.top_cpu.truncate(count);
+        self.top_memory.truncate(count);
+        self.top_io.truncate(count);
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Walking every process is by far the most expensive part of a sample, so it only happens
+    /// every `processes.interval`. Processes that exited since are dropped by sysinfo.
+    fn refresh_processes(&mut self, now: Instant) {
+        if self.processes.count == 0 || self.next_processes.is_some_and(|next| now < next) {
+            return;
+        }
+        // CPU usage is measured between two walks, so the first one is followed right away by
+        // another on the next sample.
+        self.next_processes = Some(match self.next_processes {
+            Some(_) => now + self.processes.interval.duration(),
+            None => now,
+        });
+        self.system
+            .refresh_processes(ProcessesToUpdate::All, true /*remove_dead_processes*/);
+        let all = processes::processes(&self.system, &mut self.process_io, now);
+        let count = self.processes.count;
+        self.top_cpu = processes::top(&all, count, |p| p.cpu);
+        self.top_memory = processes::top(&all, count, |p| p.memory as f64);
+        let busy = all
+            .iter()
+            .filter(|p| p.io_rate() > 0.)
+            .cloned()
+            .collect_vec();
+        self.top_io = processes::top(&busy, count, ProcessSample::io_rate);
+    }
+
+    pub fn sample(&mut self, now: Instant) -> Sample {
+        self.system.refresh_cpu_all();
+        self.system.refresh_memory();
+        self.disks.refresh(true /*remove_not_listed_disks*/);
+        self.networks.refresh(true /*remove_not_listed_interfaces*/);
+
+        // Calculate average CPU usage across all cores
+        let cpus = self.system.cpus();
+        let total_usage: f32 = cpus.iter().map(|cpu| cpu.cpu_usage()).sum();
+        let cpu = (!cpus.is_empty()).then(|| (total_usage / cpus.len() as f32).min(100.) as f64);
+        let mut breakdowns = self.proc_stat.read();
+        let clocks = self.cpufreq.read();
+        let cores = cpus
+            .iter()
+            .map(|cpu| {
+                let index = cpu_index(cpu.name());
+                let clock = index
+                    .and_then(|index| clocks.get(&index))
+                    .copied()
+                    .unwrap_or_default();
+                let sample = CoreSample {
+                    name: cpu.name().to_owned(),
+                    usage: cpu.cpu_usage() as f64,
+                    breakdown: breakdowns.remove(cpu.name()),
+                    kind: index
+                        .and_then(|index| self.topology.place(index))
+                        .and_then(|place| place.kind),
+                    // sysinfo falls back to `/proc/cpuinfo`, which also works in most VMs.
+                    frequency: clock
+                        .frequency
+                        .or(Some(cpu.frequency() as f64).filter(|&mhz| mhz > 0.)),
+                    max_frequency: clock.max_frequency,
+                    throttled: clock.throttled,
+                };
+                (index, sample)
+            })
+            .sorted_by_key(|(index, _)| index.map(|index| self.topology.sort_key(index)))
+            .map(|(_, sample)| sample)
+            .collect();
+
+        // Rates come from the cumulative counters and the time actually elapsed between samples,
+        // since the per-refresh deltas sysinfo reports depend on how regularly we get called.
+        // Several mounts can share a device, so counters are keyed by device name.
+        let disk_read = self.read_counters.update(
+            now,
+            self.disks
+                .iter()
+                .map(|disk| (disk.name().to_owned(), disk.usage().total_read_bytes)),
+        );
+        let disk_write = self.write_counters.update(
+            now,
+            self.disks
+                .iter()
+                .map(|disk| (disk.name().to_owned(), disk.usage().total_written_bytes)),
+        );
+        self.refresh_processes(now);
+        let download = self.download_counters.update(
+            now,
+            self.networks
+                .iter()
+                .map(|(if_name, network)| (if_name.clone(), network.total_received())),
+        );
+        let upload = self.upload_counters.update(
+            now,
+            self.networks
+                .iter()
+                .map(|(if_name, network)| (if_name.clone(), network.total_transmitted())),
+        );
+
+        let load = System::load_average();
+        let breakdown = self.meminfo.read();
+        Sample {
+            cpu,
+            cores,
+            cpu_breakdown: breakdowns.remove("cpu"),
+            load: Some(LoadAverage {
+                one: load.one,
+                five: load.five,
+                fifteen: load.fifteen,
+            })
+            .filter(|&load| load != LoadAverage::default()),
+            memory: Usage::new(self.system.used_memory(), self.system.total_memory()),
+            swap: Usage::new(self.system.used_swap(), self.system.total_swap()),
+            memory_breakdown: breakdown.map(|(memory, _)| memory),
+            swap_breakdown: breakdown.map(|(_, swap)| swap),
+            disks: self
+                .disks
+                .iter()
+                .map(|disk| DiskSample {
+                    name: disk.name().to_string_lossy().into_owned(),
+                    mount_point: disk.mount_point().to_owned(),
+                    space: Usage {
+                        used: disk.total_space().saturating_sub(disk.available_space()),
+                        total: disk.total_space(),
+                    },
+                    read_bytes: disk.usage().total_read_bytes,
+                    written_bytes: disk.usage().total_written_bytes,
+                })
+                .collect(),
+            interfaces: self
+                .networks
+                .iter()
+                .map(|(name, network)| InterfaceSample {
+                    name: name.clone(),
+                    received_bytes: network.total_received(),
+                    transmitted_bytes: network.total_transmitted(),
+                })
+                .collect(),
+            sensors: self.hwmon.read(),
+            battery: self.power_supply.read(),
+            top_cpu: self.top_cpu.clone(),
+            top_memory: self.top_memory.clone(),
+            top_io: self.top_io.clone(),
+            disk_read,
+            disk_write,
+            download,
+            upload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_percent() {
+        let load = LoadAverage {
+            one: 6.,
+            five: 2.,
+            fifteen: 0.5,
+        };
+        assert_eq!(
+            load.percent_of(4),
+            LoadAverage {
+                one: 150.,
+                five: 50.,
+                fifteen: 12.5,
+            }
+        );
+        assert_eq!(load.percent_of(0).one, 600.);
+    }
+
+    #[test]
+    fn test_usage_without_total() {
+        assert_eq!(Usage::new(0, 0), None);
+        let usage = Usage::new(1, 4).unwrap();
+        assert_eq!(usage.frac(), Some(0.25));
+        assert_eq!(usage.percent(), Some(25.));
+        // Disks can report no size, e.g. while they're being set up.
+        let empty = Usage { used: 0, total: 0 };
+        assert_eq!(empty.frac(), None);
+        assert_eq!(empty.percent(), None);
+    }
+
+    #[test]
+    fn test_configure_without_processes() {
+        let process = |pid| ProcessSample {
+            pid,
+            name: format!("process{pid}"),
+            cpu: 1.,
+            memory: 1024,
+            read_rate: 0.,
+            write_rate: 0.,
+        };
+        let mut sampler = Sampler::new();
+        sampler.top_cpu = (1..=5).map(process).collect();
+        sampler.top_memory = sampler.top_cpu.clone();
+        sampler.top_io = sampler.top_cpu.clone();
+        let next = Instant::now();
+        sampler.next_processes = Some(next);
+
+        let mut config = Config::default();
+        config.processes.count = 2;
+        sampler.configure(&config);
+        assert_eq!(sampler.top_cpu, [process(1), process(2)]);
+        assert_eq!(sampler.top_memory.len(), 2);
+        assert_eq!(sampler.top_io.len(), 2);
+        // Fewer processes don't need another walk.
+        assert_eq!(sampler.next_processes, Some(next));
+
+        config.processes.count = 0;
+        sampler.configure(&config);
+        assert!(sampler.top_cpu.is_empty());
+        assert!(sampler.top_memory.is_empty());
+        assert!(sampler.top_io.is_empty());
+    }
+}
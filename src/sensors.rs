@@ -0,0 +1,254 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools as _;
+use serde::Serialize;
+
+use crate::threshold::{Level, Thresholds};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorKind {
+    /// Degrees Celsius.
+    Temperature,
+    /// Revolutions per minute.
+    Fan,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SensorReading {
+    /// Chip and sensor name, e.g. "coretemp Package id 0" or "nvme Composite". Chips that share
+    /// a name get their device added, as in "nvme Composite (nvme1)".
+    pub label: String,
+    pub kind: SensorKind,
+    pub value: f64,
+    /// Temperature the hardware considers critical, if it reports one.
+    pub critical: Option<f64>,
+}
+
+impl SensorReading {
+    /// How hot a temperature sensor is, either by the configured thresholds or the hardware's
+    /// own critical limit. Fans are always normal.
+    pub fn level(&self, thresholds: &Thresholds) -> Level {
+        match self.kind {
+            SensorKind::Fan => Level::Normal,
+            SensorKind::Temperature if self.critical.is_some_and(|c| self.value >= c) => {
+                Level::Critical
+            }
+            SensorKind::Temperature => thresholds.level(self.value),
+        }
+    }
+}
+
+/// Reads temperatures and fan speeds from the kernel's hwmon interface.
+pub struct Hwmon {
+    root: PathBuf,
+}
+
+impl Hwmon {
+    /// Reads from `root` instead of `/sys/class/hwmon`, e.g. a directory of test fixtures.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Hwmon { root: root.into() }
+    }
+
+    /// Current value of every sensor, ordered by chip and then sensor number. Sensors that fail
+    /// to read, like those of a device that is powered down, are left out.
+    pub fn read(&self) -> Vec<SensorReading> {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+        let chips = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .sorted_by_key(|path| natural_key(path))
+            .map(|chip| (chip_name(&chip), chip))
+            .collect_vec();
+        let name_counts = chips.iter().counts_by(|(name, _)| name.clone());
+        chips
+            .iter()
+            .flat_map(|(name, chip)| {
+                // Chips of the same kind, like two NVMe drives, are told apart by their device.
+                let device = (name_counts[name] > 1).then(|| device_name(chip));
+                read_chip(chip, name, device.as_deref())
+            })
+            .collect()
+    }
+}
+
+impl Default for Hwmon {
+    fn default() -> Self {
+        Hwmon::new("/sys/class/hwmon")
+    }
+}
+
+/// The driver's name for the chip, e.g. "coretemp", or else its hwmon directory's.
+fn chip_name(chip: &Path) -> String {
+    fs::read_to_string(chip.join("name"))
+        .map(|name| name.trim().to_owned())
+        .unwrap_or_else(|_| file_name(chip))
+}
+
+/// Name of the device the chip belongs to, e.g. "nvme0", or else its hwmon directory's.
+fn device_name(chip: &Path) -> String {
+    fs::read_link(chip.join("device")).map_or_else(|_| file_name(chip), |device| file_name(&device))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Readings of `chip`, labeled with `chip_name` and, if given, `device` to keep the labels unique.
+fn read_chip(chip: &Path, chip_name: &str, device: Option<&str>) -> Vec<SensorReading> {
+    let read = |file: &str| fs::read_to_string(chip.join(file)).ok();
+    let Ok(entries) = fs::read_dir(chip) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|file| {
+            let sensor = file.strip_suffix("_input")?;
+            let (kind, scale) = if sensor.starts_with("temp") {
+                (SensorKind::Temperature, 1000.)
+            } else if sensor.starts_with("fan") {
+                (SensorKind::Fan, 1.)
+            } else {
+                return None;
+            };
+            let number: u32 = sensor
+                .trim_start_matches(char::is_alphabetic)
+                .parse()
+                .ok()?;
+            let value = read(&file)?.trim().parse::<f64>().ok()? / scale;
+            let label = read(&format!("{sensor}_label"))
+                .map(|label| label.trim().to_owned())
+                .unwrap_or_else(|| sensor.to_owned());
+            let critical = read(&format!("{sensor}_crit"))
+                .and_then(|crit| crit.trim().parse::<f64>().ok())
+                .map(|crit| crit / scale);
+            Some((
+                (kind == SensorKind::Fan, number),
+                SensorReading {
+                    label: match device {
+                        Some(device) => format!("{chip_name} {label} ({device})"),
+                        None => format!("{chip_name} {label}"),
+                    },
+                    kind,
+                    value,
+                    critical,
+                },
+            ))
+        })
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, reading)| reading)
+        .collect()
+}
+
+/// Sorts "hwmon10" after "hwmon9".
+fn natural_key(path: &Path) -> (String, u64) {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number = name[prefix.len()..].parse().unwrap_or_default();
+    (prefix.to_owned(), number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::write;
+
+    #[test]
+    fn test_read_hwmon() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(root, "hwmon10/name", "thinkpad\n");
+        write(root, "hwmon10/fan1_input", "2400\n");
+        write(root, "hwmon2/name", "coretemp\n");
+        write(root, "hwmon2/temp1_input", "45000\n");
+        write(root, "hwmon2/temp1_label", "Package id 0\n");
+        write(root, "hwmon2/temp1_crit", "100000\n");
+        write(root, "hwmon2/temp10_input", "41500\n");
+        write(root, "hwmon2/temp2_input", "40000\n");
+        write(root, "hwmon9/name", "nvme\n");
+        write(root, "hwmon9/temp1_input", "38850\n");
+        write(root, "hwmon9/temp1_label", "Composite\n");
+        // Unreadable and unrelated files are skipped.
+        write(root, "hwmon9/temp2_input", "");
+        write(root, "hwmon9/in0_input", "1000\n");
+
+        let readings = Hwmon::new(root).read();
+        let summary = readings
+            .iter()
+            .map(|r| (r.label.as_str(), r.kind, r.value, r.critical))
+            .collect_vec();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "coretemp Package id 0",
+                    SensorKind::Temperature,
+                    45.,
+                    Some(100.)
+                ),
+                ("coretemp temp2", SensorKind::Temperature, 40., None),
+                ("coretemp temp10", SensorKind::Temperature, 41.5, None),
+                ("nvme Composite", SensorKind::Temperature, 38.85, None),
+                ("thinkpad fan1", SensorKind::Fan, 2400., None),
+            ]
+        );
+
+        assert!(Hwmon::new(root.join("missing")).read().is_empty());
+
+        // The hardware's critical limit applies even below the configured thresholds.
+        let thresholds = Thresholds::new(80., 95.);
+        assert_eq!(readings[1].level(&thresholds), Level::Normal);
+        let mut package = readings[0].clone();
+        package.value = 85.;
+        assert_eq!(package.level(&thresholds), Level::Warning);
+        package.critical = Some(85.);
+        assert_eq!(package.level(&thresholds), Level::Critical);
+    }
+
+    #[test]
+    fn test_read_hwmon_same_chip_names() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        for (hwmon, device) in [("hwmon1", "nvme0"), ("hwmon3", "nvme1")] {
+            write(root, &format!("{hwmon}/name"), "nvme\n");
+            write(root, &format!("{hwmon}/temp1_input"), "38850\n");
+            write(root, &format!("{hwmon}/temp1_label"), "Composite\n");
+            std::os::unix::fs::symlink(
+                format!("../../devices/{device}"),
+                root.join(hwmon).join("device"),
+            )
+            .unwrap();
+        }
+        // Without a device link, the hwmon directory tells them apart.
+        write(root, "hwmon0/name", "acpitz\n");
+        write(root, "hwmon0/temp1_input", "27800\n");
+        write(root, "hwmon2/name", "acpitz\n");
+        write(root, "hwmon2/temp1_input", "29800\n");
+        write(root, "hwmon4/name", "coretemp\n");
+        write(root, "hwmon4/temp1_input", "45000\n");
+
+        let labels = Hwmon::new(root)
+            .read()
+            .into_iter()
+            .map(|reading| reading.label)
+            .collect_vec();
+        assert_eq!(
+            labels,
+            [
+                "acpitz temp1 (hwmon0)",
+                "nvme Composite (nvme0)",
+                "acpitz temp1 (hwmon2)",
+                "nvme Composite (nvme1)",
+                "coretemp temp1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    sync::mpsc::RecvTimeoutError,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    bar,
+    config::Config,
+    sample::{Sample, Sampler},
+};
+
+/// Version of the records printed by `widget stream --format json`, one JSON object per line,
+/// which look like
+///
+/// ```text
+/// {"schema_version":2,"timestamp":1760790000.25,"cpu":12.5,
+///  "cores":[{"name":"cpu0","usage":20.1,"breakdown":{"user":15.0,"nice":0.0,"system":4.1,
+///    "iowait":1.0,"irq":0.0,"steal":0.0},"kind":"performance","frequency":4200.0,
+///    "max_frequency":4800.0,"throttled":false}, ...],
+///  "cpu_breakdown":{"user":9.8, ...},
+///  "load":{"one":0.42,"five":0.61,"fifteen":0.55},
+///  "memory":{"used":8123456512,"total":33324154880},"swap":{"used":0,"total":8589934592},
+///  "memory_breakdown":{"total":33324154880,"used":7012345856,"shared":512000000,
+///    "buffers":102400000,"cache":9876543210,"huge_pages":0,"available":25200698368},
+///  "swap_breakdown":{"zram":{"used":0,"total":8589934592},"disk":{"used":0,"total":0},
+///    "zswapped":0},
+///  "disks":[{"name":"/dev/nvme0n1p2","mount_point":"/","used":123,"total":456,
+///    "read_bytes":789,"written_bytes":1011}, ...],
+///  "interfaces":[{"name":"wlan0","received_bytes":1213,"transmitted_bytes":1415}, ...],
+///  "sensors":[{"label":"coretemp Package id 0","kind":"temperature","value":45.0,
+///    "critical":100.0}, {"label":"thinkpad fan1","kind":"fan","value":2400.0,"critical":null}],
+///  "battery":{"charge":81.5,"state":"discharging","power":9.8,"energy":40.1,"energy_full":49.2},
+///  "top_cpu":[{"pid":4242,"name":"firefox","cpu":12.5,"memory":1073741824,
+///    "read_rate":0.0,"write_rate":8192.0}, ...],
+///  "top_memory":[...], "top_io":[...],
+///  "disk_read":0.0,"disk_write":40960.0,"download":1532.4,"upload":210.0}
+/// ```
+///
+/// `timestamp` is in seconds since the Unix epoch. Percentages go from 0 to 100. Sizes are in
+/// bytes, rates in bytes per second, frequencies in MHz, power in watts and energy in
+/// watt-hours. Metrics that can't be measured, like `swap` on a machine without any, `load` where
+/// the system doesn't report one or the rates with no disks or interfaces, are null rather than 0. Cores are ordered with SMT siblings next
+/// to each other, and their `kind` is "performance" or "efficiency" on hybrid CPUs and null
+/// otherwise. Adding fields keeps the schema version; renaming, removing or changing the meaning
+/// of one bumps it.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct Record<'a> {
+    schema_version: u32,
+    timestamp: f64,
+    #[serde(flatten)]
+    sample: &'a Sample,
+}
+
+impl<'a> Record<'a> {
+    fn new(sample: &'a Sample, time: SystemTime) -> Self {
+        Record {
+            schema_version: SCHEMA_VERSION,
+            timestamp: time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            sample,
+        }
+    }
+}
+
+/// What `widget stream` prints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Versioned records with everything in the sample.
+    Json,
+    /// A summary for a Waybar custom module with `"return-type": "json"`.
+    Waybar,
+    /// The i3bar/swaybar protocol. Clicking a block toggles its details.
+    I3bar,
+}
+
+/// Prints a status every `interval`, stopping after `count` of them if given or once stdout is
+/// closed.
+pub fn run(config: &Config, format: Format, interval: Duration, count: Option<u64>) -> Result<()> {
+    match stream(config, format, interval, count) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+fn stream(
+    config: &Config,
+    format: Format,
+    interval: Duration,
+    count: Option<u64>,
+) -> io::Result<()> {
+    let mut sampler = Sampler::new();
+    sampler.configure(config);
+    let mut stdout = io::stdout().lock();
+    let mut clicks = (format == Format::I3bar).then(bar::read_clicks);
+    let mut expanded = HashSet::new();
+    if format == Format::I3bar {
+        writeln!(stdout, "{}\n[", bar::I3BAR_HEADER)?;
+    }
+
+    // The first sample is only a baseline for the rates.
+    let mut next_sample = Instant::now();
+    sampler.sample(next_sample);
+    let mut latest = None;
+    for _ in 0..count.unwrap_or(u64::MAX) {
+        next_sample += interval;
+        // Clicks get an updated status right away instead of at the next sample.
+        loop {
+            let timeout = next_sample.saturating_duration_since(Instant::now());
+            let Some(receiver) = &clicks else {
+                thread::sleep(timeout);
+                break;
+            };
+            match receiver.recv_timeout(timeout) {
+                Ok(click) => {
+                    let Some(name) = click.name else {
+                        continue;
+                    };
+                    if !expanded.remove(&name) {
+                        expanded.insert(name);
+                    }
+                    if let Some(sample) = &latest {
+                        write_status(&mut stdout, config, format, sample, &expanded)?;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => clicks = None,
+            }
+        }
+        let now = Instant::now();
+        // Don't try to catch up on samples missed while suspended.
+        if now > next_sample + interval {
+            next_sample = now;
+        }
+
+        let sample = sampler.sample(now);
+        write_status(&mut stdout, config, format, &sample, &expanded)?;
+        latest = Some(sample);
+    }
+    Ok(())
+}
+
+fn write_status(
+    out: &mut impl Write,
+    config: &Config,
+    format: Format,
+    sample: &Sample,
+    expanded: &HashSet<String>,
+) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer(&mut *out, &Record::new(sample, SystemTime::now()))?;
+            writeln!(out)?;
+        }
+        Format::Waybar => {
+            serde_json::to_writer(&mut *out, &bar::waybar_status(sample, config))?;
+            writeln!(out)?;
+        }
+        // Each status line is an element of an array that never ends.
+        Format::I3bar => {
+            serde_json::to_writer(&mut *out, &bar::i3bar_status(sample, config, expanded))?;
+            writeln!(out, ",")?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::{CoreSample, Usage};
+
+    #[test]
+    fn test_record_schema() {
+        let sample = Sample {
+            cpu: Some(50.),
+            cores: vec![CoreSample {
+                name: "cpu0".to_owned(),
+                usage: 50.,
+                breakdown: None,
+                kind: None,
+                frequency: None,
+                max_frequency: None,
+                throttled: false,
+            }],
+            memory: Usage::new(1, 4),
+            disk_read: Some(0.),
+            disk_write: Some(40960.),
+            ..Sample::default()
+        };
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1500);
+        let record = serde_json::to_value(Record::new(&sample, time)).unwrap();
+        assert_eq!(
+            record,
+            serde_json::json!({
+                "schema_version": 2,
+                "timestamp": 1.5,
+                "cpu": 50.0,
+                "cores": [{"name": "cpu0", "usage": 50.0, "breakdown": null, "kind": null,
+                    "frequency": null, "max_frequency": null, "throttled": false}],
+                "cpu_breakdown": null,
+                "load": null,
+                "memory": {"used": 1, "total": 4},
+                "swap": null,
+                "memory_breakdown": null,
+                "swap_breakdown": null,
+                "disks": [],
+                "interfaces": [],
+                "sensors": [],
+                "battery": null,
+                "top_cpu": [],
+                "top_memory": [],
+                "top_io": [],
+                "disk_read": 0.0,
+                "disk_write": 40960.0,
+                "download": null,
+                "upload": null,
+            })
+        );
+    }
+}
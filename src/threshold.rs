@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+/// Nerd Font warning sign shown before the label of a metric past its warning threshold.
+pub const WARNING_ICON: &str = "\u{f071}";
+
+/// How concerning a metric's current value is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Level {
+    /// The color to draw a metric in, given its color when everything is fine.
+    pub fn color(self, normal: (f64, f64, f64)) -> (f64, f64, f64) {
+        match self {
+            Level::Normal => normal,
+            Level::Warning => (1., 190. / 255., 60. / 255.),
+            Level::Critical => (1., 64. / 255., 64. / 255.),
+        }
+    }
+
+    /// Prefixes `label` with a warning icon unless the level is normal.
+    pub fn label(self, label: &str) -> String {
+        match self {
+            Level::Normal => label.to_owned(),
+            Level::Warning | Level::Critical => format!("{WARNING_ICON} {label}"),
+        }
+    }
+}
+
+/// Percentages at which a metric turns into a warning or becomes critical.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    pub warn: Option<f64>,
+    pub critical: Option<f64>,
+}
+
+impl Thresholds {
+    pub const fn new(warn: f64, critical: f64) -> Self {
+        Thresholds {
+            warn: Some(warn),
+            critical: Some(critical),
+        }
+    }
+
+    pub fn level(&self, percent: f64) -> Level {
+        let reached = |threshold: Option<f64>| threshold.is_some_and(|t| percent >= t);
+        if reached(self.critical) {
+            Level::Critical
+        } else if reached(self.warn) {
+            Level::Warning
+        } else {
+            Level::Normal
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_threshold_level() {
+        let thresholds = Thresholds::new(80., 95.);
+        assert_eq!(thresholds.level(20.), Level::Normal);
+        assert_eq!(thresholds.level(80.), Level::Warning);
+        assert_eq!(thresholds.level(98.), Level::Critical);
+        assert_eq!(thresholds.level(f64::NAN), Level::Normal);
+
+        let critical_only = Thresholds {
+            warn: None,
+            critical: Some(90.),
+        };
+        assert_eq!(critical_only.level(85.), Level::Normal);
+        assert_eq!(critical_only.level(90.), Level::Critical);
+        assert_eq!(Thresholds::default().level(100.), Level::Normal);
+
+        assert_eq!(Level::Normal.label("MEM"), "MEM");
+        assert_eq!(Level::Critical.label("MEM"), "\u{f071} MEM");
+    }
+
+    #[test]
+    fn test_parse_thresholds_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [thresholds]
+            cpu = { warn = 90 }
+            disk = { warn = 70, critical = 90 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.thresholds.cpu.warn, Some(90.));
+        assert_eq!(config.thresholds.cpu.critical, None);
+        assert_eq!(config.thresholds.disk, Thresholds::new(70., 90.));
+        assert_eq!(config.thresholds.memory, Thresholds::new(80., 95.));
+        assert_eq!(config.thresholds.load, Thresholds::new(100., 200.));
+    }
+}
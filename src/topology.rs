@@ -0,0 +1,214 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::Serialize;
+
+/// Class of a core on hybrid CPUs, which mix fast and power-efficient ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuKind {
+    /// Intel's P-cores, listed by the kernel under `cpu_core`.
+    Performance,
+    /// Intel's E-cores, listed under `cpu_atom`.
+    Efficiency,
+}
+
+/// Where a logical CPU sits in the machine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuPlace {
+    pub package: u32,
+    /// Physical core within the package, shared by SMT siblings.
+    pub core: u32,
+    pub node: u32,
+    /// `None` unless the CPU is hybrid.
+    pub kind: Option<CpuKind>,
+}
+
+/// Layout of the logical CPUs, read from sysfs.
+#[derive(Debug)]
+pub struct Topology {
+    cpus: HashMap<usize, CpuPlace>,
+}
+
+impl Topology {
+    /// Reads from `root` instead of `/sys/devices`, e.g. a directory of test fixtures. CPUs it
+    /// can't find out about are left out, and everything is empty off Linux.
+    pub fn read(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let read = |path: PathBuf| fs::read_to_string(path).ok();
+
+        let mut nodes = HashMap::new();
+        if let Ok(entries) = fs::read_dir(root.join("system/node")) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let name = entry.file_name();
+                let Some(node) = name
+                    .to_str()
+                    .and_then(|name| name.strip_prefix("node"))
+                    .and_then(|id| id.parse::<u32>().ok())
+                else {
+                    continue;
+                };
+                for cpu in read(entry.path().join("cpulist"))
+                    .map(|list| parse_cpu_list(&list))
+                    .unwrap_or_default()
+                {
+                    nodes.insert(cpu, node);
+                }
+            }
+        }
+
+        let mut kinds = HashMap::new();
+        for (device, kind) in [
+            ("cpu_core", CpuKind::Performance),
+            ("cpu_atom", CpuKind::Efficiency),
+        ] {
+            for cpu in read(root.join(device).join("cpus"))
+                .map(|list| parse_cpu_list(&list))
+                .unwrap_or_default()
+            {
+                kinds.insert(cpu, kind);
+            }
+        }
+
+        let mut cpus = HashMap::new();
+        if let Ok(entries) = fs::read_dir(root.join("system/cpu")) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let name = entry.file_name();
+                let Some(cpu) = name.to_str().and_then(cpu_index) else {
+                    continue;
+                };
+                let topology = entry.path().join("topology");
+                let id = |file: &str| read(topology.join(file))?.trim().parse::<u32>().ok();
+                let (Some(package), Some(core)) = (id("physical_package_id"), id("core_id")) else {
+                    continue;
+                };
+                let node = nodes.get(&cpu).copied().unwrap_or_default();
+                cpus.insert(
+                    cpu,
+                    CpuPlace {
+                        package,
+                        core,
+                        node,
+                        kind: kinds.get(&cpu).copied(),
+                    },
+                );
+            }
+        }
+        Topology { cpus }
+    }
+
+    pub fn place(&self, cpu: usize) -> Option<CpuPlace> {
+        self.cpus.get(&cpu).copied()
+    }
+
+    /// Orders CPUs so SMT siblings are next to each other and P-cores come before E-cores,
+    /// rather than in the order the kernel numbers them. CPUs it doesn't know go last.
+    pub fn sort_key(&self, cpu: usize) -> (bool, Option<CpuKind>, u32, u32, usize) {
+        match self.place(cpu) {
+            Some(place) => (false, place.kind, place.package, place.core, cpu),
+            None => (true, None, 0, 0, cpu),
+        }
+    }
+}
+
+impl FromIterator<(usize, CpuPlace)> for Topology {
+    fn from_iter<I: IntoIterator<Item = (usize, CpuPlace)>>(places: I) -> Self {
+        Topology {
+            cpus: places.into_iter().collect(),
+        }
+    }
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Topology::read("/sys/devices")
+    }
+}
+
+/// Index of a CPU named like "cpu12", as sysfs and sysinfo name them.
+pub fn cpu_index(name: &str) -> Option<usize> {
+    name.strip_prefix("cpu")?.parse().ok()
+}
+
+/// Parses the kernel's CPU list format, e.g. "0-3,8-11,16".
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    list.trim()
+        .split(',')
+        .filter_map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            Some(start.parse::<usize>().ok()?..=end.parse().ok()?)
+        })
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools as _;
+
+    use super::*;
+    use crate::fixture::write;
+
+    #[test]
+    fn test_read_topology() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        // Two nodes with two cores each, with SMT siblings numbered after all first threads.
+        for (cpu, core, node) in [(0, 0, 0), (1, 1, 0), (2, 0, 1), (3, 1, 1)] {
+            for (cpu, core) in [(cpu, core), (cpu + 4, core)] {
+                let topology = format!("system/cpu/cpu{cpu}/topology");
+                write(root, &format!("{topology}/core_id"), &format!("{core}\n"));
+                write(
+                    root,
+                    &format!("{topology}/physical_package_id"),
+                    &format!("{node}\n"),
+                );
+            }
+        }
+        write(root, "system/cpu/online", "0-7\n");
+        write(root, "system/node/node0/cpulist", "0-1,4-5\n");
+        write(root, "system/node/node1/cpulist", "2-3,6-7\n");
+
+        let topology = Topology::read(root);
+        assert_eq!(
+            topology.place(6),
+            Some(CpuPlace {
+                package: 1,
+                core: 0,
+                node: 1,
+                kind: None,
+            })
+        );
+        assert_eq!(topology.place(5).unwrap().node, 0);
+        assert_eq!(topology.place(8), None);
+        // Siblings 2 and 6 share a core.
+        let order = (0..9)
+            .sorted_by_key(|&cpu| topology.sort_key(cpu))
+            .collect_vec();
+        assert_eq!(order, [0, 4, 1, 5, 2, 6, 3, 7, 8]);
+        assert_eq!(Topology::read(root.join("missing")).place(0), None);
+
+        // A hybrid CPU with two P-cores with SMT and two E-cores without, numbered P-cores first
+        // but with E-cores on lower core IDs.
+        let hybrid = tempfile::tempdir().unwrap();
+        let hybrid = hybrid.path();
+        for (cpu, core) in [(0, 8), (1, 8), (2, 9), (3, 9), (4, 0), (5, 1)] {
+            let topology = format!("system/cpu/cpu{cpu}/topology");
+            write(hybrid, &format!("{topology}/core_id"), &format!("{core}\n"));
+            write(hybrid, &format!("{topology}/physical_package_id"), "0\n");
+        }
+        write(hybrid, "cpu_core/cpus", "0-3\n");
+        write(hybrid, "cpu_atom/cpus", "4-5\n");
+        let topology = Topology::read(hybrid);
+        assert_eq!(topology.place(1).unwrap().kind, Some(CpuKind::Performance));
+        assert_eq!(topology.place(4).unwrap().kind, Some(CpuKind::Efficiency));
+        let order = (0..6)
+            .sorted_by_key(|&cpu| topology.sort_key(cpu))
+            .collect_vec();
+        assert_eq!(order, [0, 1, 2, 3, 4, 5]);
+
+        assert_eq!(parse_cpu_list("0-2,8,10-11\n"), [0, 1, 2, 8, 10, 11]);
+        assert!(parse_cpu_list("\n").is_empty());
+        assert_eq!(cpu_index("cpu12"), Some(12));
+        assert_eq!(cpu_index("cpufreq"), None);
+    }
+}
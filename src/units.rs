@@ -0,0 +1,138 @@
+use serde::Deserialize;
+
+/// Whether multiples step by 1024 (KiB, MiB, ...) or by 1000 (kB, MB, ...).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitBase {
+    #[default]
+    Iec,
+    Si,
+}
+
+/// What quantity is displayed for a byte count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataUnit {
+    #[default]
+    Bytes,
+    Bits,
+}
+
+/// Formats byte counts and rates for labels, e.g. "15.3MiB" or "1.2Mbit/s".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnitFormat {
+    pub base: UnitBase,
+    pub unit: DataUnit,
+    /// Decimal places shown once a prefix is applied.
+    pub precision: usize,
+}
+
+impl UnitFormat {
+    pub fn amount(&self, bytes: f64) -> String {
+        let (factor, prefixes): (f64, [&str; 6]) = match self.base {
+            UnitBase::Iec => (1024., ["", "Ki", "Mi", "Gi", "Ti", "Pi"]),
+            UnitBase::Si => (1000., ["", "k", "M", "G", "T", "P"]),
+        };
+        let (mut val, symbol) = match self.unit {
+            DataUnit::Bytes => (bytes, "B"),
+            DataUnit::Bits => (bytes * 8., "bit"),
+        };
+
+        // Step up a prefix whenever the value would otherwise be printed as e.g. "1024.0KiB".
+        let precision = |exp| if exp == 0 { 0 } else { self.precision };
+        let mut exp = 0;
+        while exp + 1 < prefixes.len() && round_to(val, precision(exp)) >= factor {
+            val /= factor;
+            exp += 1;
+        }
+        format!("{val:.*}{}{symbol}", precision(exp), prefixes[exp])
+    }
+
+    pub fn rate(&self, bytes_per_second: f64) -> String {
+        format!("{}/s", self.amount(bytes_per_second))
+    }
+
+    /// Like `rate`, but "n/a" for rates that can't be measured.
+    pub fn rate_or_na(&self, bytes_per_second: Option<f64>) -> String {
+        bytes_per_second.map_or("n/a".to_owned(), |rate| self.rate(rate))
+    }
+}
+
+fn round_to(val: f64, decimals: usize) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (val * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_format_bytes() {
+        let iec = UnitFormat {
+            base: UnitBase::Iec,
+            unit: DataUnit::Bytes,
+            precision: 1,
+        };
+        let si = UnitFormat {
+            base: UnitBase::Si,
+            ..iec
+        };
+        let si_bits = UnitFormat {
+            unit: DataUnit::Bits,
+            ..si
+        };
+        let iec_bits = UnitFormat {
+            unit: DataUnit::Bits,
+            ..iec
+        };
+        let precise = UnitFormat {
+            precision: 3,
+            ..iec
+        };
+
+        let cases: &[(UnitFormat, u64, &str)] = &[
+            (iec, 0, "0B"),
+            (iec, 43, "43B"),
+            (iec, 999, "999B"),
+            (iec, 1000, "1000B"),
+            (iec, 1024, "1.0KiB"),
+            (iec, 1076, "1.1KiB"),
+            (iec, 1048574, "1.0MiB"),
+            (iec, 1048578, "1.0MiB"),
+            (iec, 16043212, "15.3MiB"),
+            (iec, 702227152896, "654.0GiB"),
+            (iec, 1039475162591213420, "923.2PiB"),
+            (iec, 1503947516259121342, "1335.8PiB"),
+            (si, 999, "999B"),
+            (si, 1000, "1.0kB"),
+            (si, 1076, "1.1kB"),
+            (si, 16043212, "16.0MB"),
+            (si, 702227152896, "702.2GB"),
+            (si, 1503947516259121342, "1503.9PB"),
+            (si_bits, 100, "800bit"),
+            (si_bits, 125, "1.0kbit"),
+            (si_bits, 12_500_000, "100.0Mbit"),
+            (iec_bits, 128, "1.0Kibit"),
+            (precise, 16043212, "15.300MiB"),
+            (precise, 43, "43B"),
+        ];
+        for &(format, bytes, expected) in cases {
+            assert_eq!(format.amount(bytes as f64), expected, "{format:?} {bytes}");
+        }
+
+        assert_eq!(si_bits.rate(125_000.), "1.0Mbit/s");
+        assert_eq!(iec.rate(512.), "512B/s");
+        assert_eq!(si.rate(999.7), "1.0kB/s");
+    }
+
+    #[test]
+    fn test_parse_units_config() {
+        let config: Config = toml::from_str("[units]\nbase = \"si\"\nnetwork = \"bits\"").unwrap();
+        assert_eq!(config.units.base, UnitBase::Si);
+        assert_eq!(config.units.precision, 1);
+        assert_eq!(config.units.disk, DataUnit::Bytes);
+        assert_eq!(config.units.network, DataUnit::Bits);
+    }
+}